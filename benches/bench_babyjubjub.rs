@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use rand::Rng;
+
+use crypto_rs::babyjubjub::{self, ct, Point, PrivateKey, Scalar, Signature, BASE8};
+use crypto_rs::poseidon::Fr;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let s: Scalar = rng.gen();
+    let n = s.to_bigint();
+    // builds the table outside of the measurement.
+    babyjubjub::mul_base8(&s);
+
+    c.bench_function("BASE8 mul_scalar", |b| b.iter(|| BASE8.mul_scalar(&n)));
+    c.bench_function("BASE8 fixed-base table", |b| b.iter(|| babyjubjub::mul_base8(&s)));
+    c.bench_function("BASE8 constant-time table", |b| b.iter(|| ct::mul_base8(&s.to_limbs())));
+
+    let scalars: Vec<Scalar> = (0..256).map(|_| rng.gen()).collect();
+    let points: Vec<Point> = scalars.iter().map(babyjubjub::mul_base8).collect();
+    c.bench_function("multi_scalar_mul 256", |b| {
+        b.iter(|| babyjubjub::multi_scalar_mul(&points, &scalars).unwrap())
+    });
+}
+
+// `verify_batch` against `verify_poseidon` item by item, with a key per
+// item and with a single key, whose subgroup check the batch does once.
+fn verify_benchmark(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let keys: Vec<PrivateKey> = (0..256).map(|_| PrivateKey::import(rng.gen())).collect();
+    let sign = |key: &PrivateKey, rng: &mut rand::ThreadRng| {
+        let m: Fr = rng.gen();
+        (key.public(), key.sign_poseidon(&m), m)
+    };
+    let many_keys: Vec<(Point, Signature, Fr)> = keys.iter().map(|k| sign(k, &mut rng)).collect();
+    let one_key: Vec<(Point, Signature, Fr)> = (0..256).map(|_| sign(&keys[0], &mut rng)).collect();
+
+    for (name, items) in &[("256 keys", &many_keys), ("1 key", &one_key)] {
+        c.bench_function(&format!("verify_poseidon 256, {}", name), |b| {
+            b.iter(|| {
+                items
+                    .iter()
+                    .all(|(pk, sig, m)| babyjubjub::verify_poseidon(pk, sig, m).is_ok())
+            })
+        });
+        c.bench_function(&format!("verify_batch 256, {}", name), |b| {
+            b.iter(|| babyjubjub::verify_batch(items))
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark, verify_benchmark);
+criterion_main!(benches);
@@ -1,10 +1,21 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
-#[macro_use]
-extern crate ff;
 use ff::*;
+use rand::Rng;
 
-use poseidon_rs::{Fr, Poseidon};
+use crypto_rs::poseidon::kernel::{self, Kernel};
+use crypto_rs::poseidon::{self, Constants, Fr, LaneState, LANES};
+
+// the bench only measures speed, so random constants do as well as real ones.
+fn random_constants(t: usize) -> Constants {
+    let mut rng = rand::thread_rng();
+    Constants::new(
+        t, 8, 57,
+        (0..(8 + 57)).map(|_| rng.gen()).collect(),
+        (0..t).map(|_| (0..t).map(|_| rng.gen()).collect()).collect(),
+    )
+    .unwrap()
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
     let b1: Fr = Fr::from_str(
@@ -15,15 +26,72 @@ fn criterion_benchmark(c: &mut Criterion) {
         "12242166908188651009877250812424843524687801523336557272219921456462821518061",
     )
     .unwrap();
-    let mut big_arr: Vec<Fr> = Vec::new();
-    big_arr.push(b1.clone());
-    big_arr.push(b2.clone());
-    let poseidon = Poseidon::new();
+    let big_arr: Vec<Fr> = vec![b1, b2];
+    poseidon::register_params(random_constants(3));
 
     c.bench_function("hash", |b| {
-        b.iter(|| poseidon.hash(big_arr.clone()).unwrap())
+        b.iter(|| poseidon::poseidon(&big_arr, None).unwrap())
+    });
+
+    let params = random_constants(3);
+    let inp: Vec<Fr> = big_arr.iter().cycle().take(2 * 1024).cloned().collect();
+    c.bench_function("hash_many 1024", |b| {
+        b.iter(|| poseidon::hash_many(&params, &inp).unwrap())
+    });
+    // the same 1024 items through `hash` one by one, the baseline for
+    // `hash_many`.
+    c.bench_function("hash 1024", |b| {
+        b.iter(|| {
+            inp.chunks(2)
+                .map(|item| {
+                    let mut state = vec![item[0], item[1], Fr::zero()];
+                    poseidon::hash(&params, &mut state)
+                })
+                .collect::<Vec<Fr>>()
+        })
     });
+
+    // single-threaded: one lane permutation against LANES scalar ones.
+    let lanes: LaneState = vec![[b1; LANES]; 3];
+    c.bench_function("hash_lanes", |b| {
+        b.iter(|| poseidon::hash_lanes(&params, &mut lanes.clone()))
+    });
+    c.bench_function("hash x LANES", |b| {
+        b.iter(|| {
+            for _ in 0..LANES {
+                poseidon::hash(&params, &mut [b1, b2, Fr::zero()]);
+            }
+        })
+    });
+}
+
+// every supported kernel on a chain of dependent multiplications, a full
+// permutation and a batch, to check the order of `Kernel::ALL`.
+fn kernel_benchmark(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let params = random_constants(3);
+    let inp: Vec<Fr> = (0..2 * 1024).map(|_| rng.gen()).collect();
+    let (x, y): (Fr, Fr) = (rng.gen(), rng.gen());
+
+    for k in Kernel::ALL.iter().filter(|k| k.is_supported()) {
+        kernel::set_kernel(*k);
+        c.bench_function(&format!("kernel {} mul x1000", k.name()), |b| {
+            b.iter(|| {
+                let mut a = x;
+                for _ in 0..1000 {
+                    kernel::mul(&mut a, &y);
+                }
+                a
+            })
+        });
+        c.bench_function(&format!("kernel {} hash", k.name()), |b| {
+            b.iter(|| poseidon::hash(&params, &mut [x, y, Fr::zero()]))
+        });
+        c.bench_function(&format!("kernel {} hash_many 1024", k.name()), |b| {
+            b.iter(|| poseidon::hash_many(&params, &inp).unwrap())
+        });
+    }
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, kernel_benchmark);
 criterion_main!(benches);
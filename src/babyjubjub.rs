@@ -1,13 +1,25 @@
 // BabyJubJub elliptic curve implementation in Rust.
 // For LICENSE check https://github.com/arnaucube/babyjubjub-rs
 
+use std::collections::{HashMap, HashSet};
+use std::ops::{Add, Mul, Neg, Sub};
+
+use blake_hash::Digest;
 use ff::*;
-use pyo3::prelude::*;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
+use rand::Rng;
+use rayon::prelude::*;
 
+use crate::error::{Error, Rejection};
 use crate::poseidon;
+use crate::poseidon::kernel;
+
+pub mod ct;
+pub mod mul;
+pub mod scalar;
 
 pub type Fr = poseidon::Fr; // alias
+pub use self::scalar::Scalar;
 
 lazy_static! {
     static ref D: Fr = Fr::from_str("168696").unwrap();
@@ -16,7 +28,24 @@ lazy_static! {
     //     b"21888242871839275222246405745257275088548364400416034343698204186575808495617",10
     // )
     //     .unwrap();
-    static ref B8: Point = Point {
+    pub static ref SUBORDER: BigInt = BigInt::parse_bytes(
+        b"2736030358979909402780800718157159386076813972158567259200215660948447373041",
+        10,
+    )
+    .unwrap();
+    /// circomlib's `Base8`, the generator of the prime-order subgroup that
+    /// keys and signatures are made over.
+    pub static ref BASE8: Point = Point {
+        x: Fr::from_str(
+            "5299619240641551281634865583518297030282874472190772894086521144482721001553",
+        ).unwrap(),
+        y: Fr::from_str(
+            "16950150798460657717958625567821834550301663161624707787222815936182638968203",
+        ).unwrap(),
+    };
+    /// Another generator of the subgroup, the base of the legacy
+    /// `eddsa_verify` binding. Not circomlib's, see `BASE8`.
+    pub static ref B8: Point = Point {
         x: Fr::from_str(
             "16540640123574156134436876038791482806971768689494387082833631921987005038935",
         ).unwrap(),
@@ -24,6 +53,42 @@ lazy_static! {
             "20819045374670962167435360035096875258406992893633759881276124905556507972311",
         ).unwrap(),
     };
+    /// circomlib's width 6 Poseidon, which hashes the signed messages.
+    static ref EDDSA_PARAMS: poseidon::Constants = poseidon::circomlib_params(6).unwrap();
+    /// `BASE8_TABLE[15 * i + j] = (j + 1) * 16^i * BASE8` with `Z = 1`, 15
+    /// entries for each 4-bit window of a 256-bit scalar, for `mul_base8`.
+    static ref BASE8_TABLE: Vec<PointExtended> = {
+        let mut table = Vec::with_capacity(64 * 15);
+        let mut base = BASE8.extended();
+        for _ in 0..64 {
+            let mut acc = base.clone();
+            for _ in 0..15 {
+                table.push(acc.clone());
+                acc = acc.add(&base);
+            }
+            base = acc;
+        }
+        batch_normalize(&table)
+    };
+}
+
+/// `n * BASE8` from the fixed-base table: one mixed addition per 4-bit window
+/// and no doublings. Skips zero windows, so it is variable time; secret
+/// scalars go through `ct::mul_base8`.
+pub fn mul_base8(n: &Scalar) -> Point {
+    mul_base8_extended(n).curve_affine()
+}
+
+pub fn mul_base8_extended(n: &Scalar) -> PointExtended {
+    let mut r = PointExtended::identity();
+    for (i, b) in n.to_bytes().iter().enumerate() {
+        for (window, digit) in [(2 * i, b & 0x0f), (2 * i + 1, b >> 4)] {
+            if digit != 0 {
+                r = r.add_mixed(&BASE8_TABLE[15 * window + digit as usize - 1]);
+            }
+        }
+    }
+    r
 }
 
 #[derive(Clone, Debug)]
@@ -34,37 +99,38 @@ pub struct PointProjective {
 }
 
 impl PointProjective {
-    pub fn affine(&self) -> Point {
+    /// `(X/Z, Y/Z)`, or `None` for `Z = 0`, which adding points of the
+    /// curve never gives.
+    pub fn affine(&self) -> Option<Point> {
         if self.z.is_zero() {
-            return Point {
-                x: Fr::zero(),
-                y: Fr::zero(),
-            };
+            return None;
         }
 
+        let k = kernel::kernel();
         let zinv = self.z.inverse().unwrap();
         let mut x = self.x;
-        x.mul_assign(&zinv);
+        k.mul(&mut x, &zinv);
         let mut y = self.y;
-        y.mul_assign(&zinv);
+        k.mul(&mut y, &zinv);
 
-        Point { x, y }
+        Some(Point { x, y })
     }
 
     #[allow(clippy::many_single_char_names)]
     pub fn add(&self, q: &PointProjective) -> PointProjective {
         // add-2008-bbjlp https://hyperelliptic.org/EFD/g1p/auto-twisted-projective.html#doubling-dbl-2008-bbjlp
+        let k = kernel::kernel();
         let mut a = self.z;
-        a.mul_assign(&q.z);
+        k.mul(&mut a, &q.z);
         let mut b = a;
-        b.square();
+        k.square(&mut b);
         let mut c = self.x;
-        c.mul_assign(&q.x);
+        k.mul(&mut c, &q.x);
         let mut d = self.y;
-        d.mul_assign(&q.y);
+        k.mul(&mut d, &q.y);
         let mut e = *D;
-        e.mul_assign(&c);
-        e.mul_assign(&d);
+        k.mul(&mut e, &c);
+        k.mul(&mut e, &d);
         let mut f = b;
         f.sub_assign(&e);
         let mut g = b;
@@ -74,21 +140,21 @@ impl PointProjective {
         let mut x2y2 = q.x;
         x2y2.add_assign(&q.y);
         let mut aux = x1y1;
-        aux.mul_assign(&x2y2);
+        k.mul(&mut aux, &x2y2);
         aux.sub_assign(&c);
         aux.sub_assign(&d);
         let mut x3 = a;
-        x3.mul_assign(&f);
-        x3.mul_assign(&aux);
+        k.mul(&mut x3, &f);
+        k.mul(&mut x3, &aux);
         let mut ac = *A;
-        ac.mul_assign(&c);
+        k.mul(&mut ac, &c);
         let mut dac = d;
         dac.sub_assign(&ac);
         let mut y3 = a;
-        y3.mul_assign(&g);
-        y3.mul_assign(&dac);
+        k.mul(&mut y3, &g);
+        k.mul(&mut y3, &dac);
         let mut z3 = f;
-        z3.mul_assign(&g);
+        k.mul(&mut z3, &g);
 
         PointProjective {
             x: x3,
@@ -98,13 +164,230 @@ impl PointProjective {
     }
 }
 
+/// A point in extended twisted Edwards coordinates, `x = X/Z`, `y = Y/Z`
+/// and `T = XY/Z`. As `a` is a square and `d` is not, the unified `add` is
+/// complete, it also doubles and handles the identity.
 #[derive(Clone, Debug)]
-pub struct Point {
+pub struct PointExtended {
     pub x: Fr,
     pub y: Fr,
+    pub t: Fr,
+    pub z: Fr,
+}
+
+impl PointExtended {
+    pub fn identity() -> PointExtended {
+        PointExtended {
+            x: Fr::zero(),
+            y: Fr::one(),
+            t: Fr::zero(),
+            z: Fr::one(),
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.x.is_zero() && self.y == self.z
+    }
+
+    /// Compares without converting to affine, `X1*Z2 == X2*Z1` and
+    /// `Y1*Z2 == Y2*Z1`.
+    pub fn equals(&self, q: &PointExtended) -> bool {
+        let k = kernel::kernel();
+        let cross = |a: &Fr, b: &Fr| {
+            let mut l = *a;
+            k.mul(&mut l, &q.z);
+            let mut r = *b;
+            k.mul(&mut r, &self.z);
+            l == r
+        };
+        cross(&self.x, &q.x) && cross(&self.y, &q.y)
+    }
+
+    /// `(X/Z, Y/Z)`, or `None` for `Z = 0`, which adding points of the
+    /// curve never gives.
+    pub fn affine(&self) -> Option<Point> {
+        batch_affine(std::slice::from_ref(self)).pop().unwrap()
+    }
+
+    /// `affine` of the result of curve arithmetic; panics on `Z = 0`,
+    /// reached only from a `Point` built off the curve within the crate.
+    fn curve_affine(&self) -> Point {
+        self.affine().expect(Z_ZERO)
+    }
+
+    pub fn neg(&self) -> PointExtended {
+        let mut p = self.clone();
+        p.x.negate();
+        p.t.negate();
+        p
+    }
+
+    /// add-2008-hwcd https://hyperelliptic.org/EFD/g1p/auto-twisted-extended.html#addition-add-2008-hwcd
+    pub fn add(&self, q: &PointExtended) -> PointExtended {
+        let mut d = self.z;
+        kernel::kernel().mul(&mut d, &q.z);
+        self.add_with_z(q, d)
+    }
+
+    /// madd-2008-hwcd: `add` for a `q` with `Z = 1`, such as the output of
+    /// `batch_normalize`, saving a multiplication.
+    pub fn add_mixed(&self, q: &PointExtended) -> PointExtended {
+        debug_assert!(q.z == Fr::one());
+        self.add_with_z(q, self.z)
+    }
+
+    fn add_with_z(&self, q: &PointExtended, d: Fr) -> PointExtended {
+        let k = kernel::kernel();
+        let mut a = self.x;
+        k.mul(&mut a, &q.x);
+        let mut b = self.y;
+        k.mul(&mut b, &q.y);
+        let mut c = *D;
+        k.mul(&mut c, &self.t);
+        k.mul(&mut c, &q.t);
+        let mut e = self.x;
+        e.add_assign(&self.y);
+        let mut x2y2 = q.x;
+        x2y2.add_assign(&q.y);
+        k.mul(&mut e, &x2y2);
+        e.sub_assign(&a);
+        e.sub_assign(&b);
+        let mut f = d;
+        f.sub_assign(&c);
+        let mut g = d;
+        g.add_assign(&c);
+        let mut h = *A;
+        k.mul(&mut h, &a);
+        h.negate();
+        h.add_assign(&b);
+        from_efgh(e, f, g, h)
+    }
+
+    /// dbl-2008-hwcd https://hyperelliptic.org/EFD/g1p/auto-twisted-extended.html#doubling-dbl-2008-hwcd
+    pub fn double(&self) -> PointExtended {
+        let k = kernel::kernel();
+        let mut a = self.x;
+        k.square(&mut a);
+        let mut b = self.y;
+        k.square(&mut b);
+        let mut c = self.z;
+        k.square(&mut c);
+        c.double();
+        let mut d = *A;
+        k.mul(&mut d, &a);
+        let mut e = self.x;
+        e.add_assign(&self.y);
+        k.square(&mut e);
+        e.sub_assign(&a);
+        e.sub_assign(&b);
+        let mut g = d;
+        g.add_assign(&b);
+        let mut f = g;
+        f.sub_assign(&c);
+        let mut h = d;
+        h.sub_assign(&b);
+        from_efgh(e, f, g, h)
+    }
+}
+
+/// The common tail of the hwcd formulas: `X3 = E*F`, `Y3 = G*H`,
+/// `T3 = E*H`, `Z3 = F*G`.
+fn from_efgh(e: Fr, f: Fr, g: Fr, h: Fr) -> PointExtended {
+    let k = kernel::kernel();
+    let mut x = e;
+    k.mul(&mut x, &f);
+    let mut y = g;
+    k.mul(&mut y, &h);
+    let mut t = e;
+    k.mul(&mut t, &h);
+    let mut z = f;
+    k.mul(&mut z, &g);
+    PointExtended { x, y, t, z }
+}
+
+const Z_ZERO: &str = "Z = 0, reached only from points off the curve";
+
+/// Converts to affine with a single inversion, Montgomery's trick. Points
+/// with `Z = 0`, which valid points never reach, map to `None` without
+/// failing the rest of the batch.
+pub fn batch_affine(points: &[PointExtended]) -> Vec<Option<Point>> {
+    let k = kernel::kernel();
+    // prefix[i] is the product of the non-zero z before i.
+    let mut prefix = Vec::with_capacity(points.len());
+    let mut acc = Fr::one();
+    for p in points {
+        prefix.push(acc);
+        if !p.z.is_zero() {
+            k.mul(&mut acc, &p.z);
+        }
+    }
+    let mut inv = acc.inverse().unwrap();
+
+    let mut out = vec![None; points.len()];
+    for ((p, pre), o) in points.iter().zip(prefix).zip(out.iter_mut()).rev() {
+        if p.z.is_zero() {
+            continue;
+        }
+        let mut zinv = inv;
+        k.mul(&mut zinv, &pre);
+        k.mul(&mut inv, &p.z);
+        let mut x = p.x;
+        k.mul(&mut x, &zinv);
+        let mut y = p.y;
+        k.mul(&mut y, &zinv);
+        *o = Some(Point { x, y });
+    }
+    out
+}
+
+/// `batch_affine` back in extended coordinates with `Z = 1`, ready for
+/// `PointExtended::add_mixed`. Panics on `Z = 0`, see `curve_affine`.
+pub fn batch_normalize(points: &[PointExtended]) -> Vec<PointExtended> {
+    batch_affine(points)
+        .into_iter()
+        .map(|p| p.expect(Z_ZERO).extended())
+        .collect()
+}
+
+/// An affine point of the curve. Coordinates are kept reduced, so the
+/// derived `==` and `Hash` compare points. They are only set through
+/// `Point::new` or curve arithmetic, so adding and multiplying points never
+/// meets `Z = 0`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub(crate) x: Fr,
+    pub(crate) y: Fr,
 }
 
 impl Point {
+    /// `(x, y)`, checked to be on the curve; see `in_subgroup` for the
+    /// subgroup.
+    pub fn new(x: Fr, y: Fr) -> Result<Point, Error> {
+        let p = Point { x, y };
+        if !p.is_on_curve() {
+            return Err(Error::InvalidPoint(format!(
+                "({}, {}) is not on the curve", poseidon::fr_to_biguint(&x), poseidon::fr_to_biguint(&y))));
+        }
+        Ok(p)
+    }
+
+    pub fn x(&self) -> Fr {
+        self.x
+    }
+
+    pub fn y(&self) -> Fr {
+        self.y
+    }
+
+    /// The neutral element `(0, 1)`.
+    pub fn identity() -> Point {
+        Point { x: Fr::zero(), y: Fr::one() }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Point::identity()
+    }
+
     pub fn projective(&self) -> PointProjective {
         PointProjective {
             x: self.x,
@@ -113,58 +396,827 @@ impl Point {
         }
     }
 
-    pub fn mul_scalar(&self, n: &BigInt) -> Point {
-        let mut r: PointProjective = PointProjective {
-            x: Fr::zero(),
-            y: Fr::one(),
+    pub fn extended(&self) -> PointExtended {
+        let mut t = self.x;
+        kernel::kernel().mul(&mut t, &self.y);
+        PointExtended {
+            x: self.x,
+            y: self.y,
+            t,
             z: Fr::one(),
-        };
-        let mut exp: PointProjective = self.projective();
-        let (_, b) = n.to_bytes_le();
-        for i in 0..n.bits() {
-            if test_bit(&b, i as usize) {
-                r = r.add(&exp);
-            }
-            exp = exp.add(&exp);
         }
-        r.affine()
     }
 
+    pub fn mul_scalar(&self, n: &BigInt) -> Point {
+        self.mul_scalar_extended(n).curve_affine()
+    }
+
+    /// `n * self` by wNAF, see `mul::wnaf_mul`. Unlike `mul_base8`, `n` is
+    /// not reduced, so points outside the subgroup are multiplied exactly.
+    /// Variable time, see `ct::mul` for secret scalars.
+    pub fn mul_scalar_extended(&self, n: &BigInt) -> PointExtended {
+        let k = n.magnitude().to_u64_digits();
+        match n.sign() {
+            Sign::Minus => mul::wnaf_mul(&-self, &k),
+            _ => mul::wnaf_mul(self, &k),
+        }
+    }
+
+    /// Same as `==`.
     pub fn equals(&self, p: Point) -> bool {
-        self.x == p.x && self.y == p.y
+        *self == p
+    }
+
+    /// Checks `self` is on the curve and `l * self` is the identity, `l`
+    /// the order of the subgroup generated by `BASE8`.
+    pub fn in_subgroup(&self) -> bool {
+        self.is_on_curve() && mul::wnaf_mul(self, Scalar::char().as_ref()).is_identity()
+    }
+
+    /// `8 * self`, which is in the subgroup for any point of the curve.
+    pub fn mul_by_cofactor(&self) -> Point {
+        self.extended().double().double().double().curve_affine()
+    }
+
+    /// Checks `a*x^2 + y^2 == 1 + d*x^2*y^2`.
+    pub fn is_on_curve(&self) -> bool {
+        let mut x2 = self.x;
+        x2.square();
+        let mut y2 = self.y;
+        y2.square();
+
+        let mut lhs = *A;
+        lhs.mul_assign(&x2);
+        lhs.add_assign(&y2);
+
+        let mut rhs = *D;
+        rhs.mul_assign(&x2);
+        rhs.mul_assign(&y2);
+        rhs.add_assign(&Fr::one());
+
+        lhs == rhs
+    }
+
+    /// circomlib's `packPoint`: `y` as 32 little-endian bytes, with the top
+    /// bit set when `x > (p - 1) / 2`.
+    pub fn compress(&self) -> [u8; 32] {
+        let mut r = [0u8; 32];
+        poseidon::fr_to_bytes(&self.y, false, &mut r);
+        if is_negative(&self.x) {
+            r[31] |= 0x80;
+        }
+        r
     }
 }
 
+impl Neg for &Point {
+    type Output = Point;
+
+    /// `(-x, y)`.
+    fn neg(self) -> Point {
+        let mut p = self.clone();
+        p.x.negate();
+        p
+    }
+}
+
+impl Neg for Point {
+    type Output = Point;
+
+    fn neg(self) -> Point {
+        -&self
+    }
+}
+
+impl Add<&Point> for &Point {
+    type Output = Point;
+
+    fn add(self, q: &Point) -> Point {
+        self.extended().add(&q.extended()).curve_affine()
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, q: Point) -> Point {
+        &self + &q
+    }
+}
+
+impl Sub<&Point> for &Point {
+    type Output = Point;
+
+    fn sub(self, q: &Point) -> Point {
+        self.extended().add(&q.extended().neg()).curve_affine()
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, q: Point) -> Point {
+        &self - &q
+    }
+}
+
+/// `n * p` by `mul::wnaf_mul`, variable time; secret scalars go through
+/// `ct::mul`.
+impl Mul<&Scalar> for &Point {
+    type Output = Point;
+
+    fn mul(self, n: &Scalar) -> Point {
+        mul::wnaf_mul(self, &n.to_limbs()).curve_affine()
+    }
+}
+
+impl Mul<Scalar> for Point {
+    type Output = Point;
+
+    fn mul(self, n: Scalar) -> Point {
+        &self * &n
+    }
+}
+
+/// Whether `x` lies in the upper half of the field, `x > (p - 1) / 2`.
+fn is_negative(x: &Fr) -> bool {
+    let mut half = Fr::char();
+    half.div2();
+    x.into_repr() > half
+}
+
+/// circomlib's `unpackPoint`: recovers `x` from the curve equation,
+/// `x^2 = (1 - y^2) / (a - d*y^2)`. Like it, rejects `y >= p` and the
+/// points with `x = 0`, `(0, 1)` and `(0, -1)`, whose `x^2` is not a
+/// nonzero square, even though `compress` packs them.
+pub fn decompress_point(bb: [u8; 32]) -> Result<Point, Error> {
+    let sign = bb[31] & 0x80 != 0;
+    let mut y_bytes = bb;
+    y_bytes[31] &= 0x7f;
+    let y = poseidon::fr_from_bytes(&y_bytes, false)
+        .map_err(|_| Error::InvalidPoint("y is not below the modulus".to_string()))?;
+
+    let mut y2 = y;
+    y2.square();
+    let mut num = Fr::one();
+    num.sub_assign(&y2);
+    let mut den = *D;
+    den.mul_assign(&y2);
+    den.negate();
+    den.add_assign(&A);
+    let x2 = den.inverse().map(|inv| {
+        let mut x2 = num;
+        x2.mul_assign(&inv);
+        x2
+    });
+    let mut x = x2.and_then(|x2| x2.sqrt())
+        .ok_or_else(|| Error::InvalidPoint("no x for this y".to_string()))?;
+    if x.is_zero() {
+        return Err(Error::InvalidPoint("x = 0".to_string()));
+    }
+    if is_negative(&x) {
+        x.negate();
+    }
+    if sign {
+        x.negate();
+    }
+    Ok(Point { x, y })
+}
+
 #[inline]
 pub fn test_bit(b: &[u8], i: usize) -> bool {
     b[i / 8] & (1 << (i % 8)) != 0
 }
 
-#[pyfunction]
-pub fn eddsa_verify(inps: [String; 6]) -> bool {
-    let [x1, x2, rx, ry, ss, msg] = inps;
-    let pk: Point = Point { 
-        x: Fr::from_str(&x1).unwrap(), 
-        y: Fr::from_str(&x2).unwrap() 
-    };
-    let r = Point {
-        x: Fr::from_str(&rx).unwrap(),
-        y: Fr::from_str(&ry).unwrap(),
-    };
-    let s: BigInt = BigInt::parse_bytes(ss.as_bytes(), 10).unwrap();
-    let m = Fr::from_str(&msg).unwrap();
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub r_b8: Point,
+    pub s: Scalar,
+}
+
+impl Signature {
+    /// A signature with an integer `S`, which is rejected with
+    /// `Rejection::SOutOfRange` unless it is below the subgroup order: `S`
+    /// and `S + l` act the same on `BASE8`, so accepting both would make
+    /// signatures malleable.
+    pub fn new(r_b8: Point, s: &BigInt) -> Result<Signature, Error> {
+        let s = Scalar::from_bigint(s).map_err(|_| Error::SignatureRejected(Rejection::SOutOfRange))?;
+        Ok(Signature { r_b8, s })
+    }
+
+    /// circomlib's `packSignature`: the compressed `R8` followed by `S` as
+    /// 32 little-endian bytes.
+    pub fn compress(&self) -> [u8; 64] {
+        let mut b = [0u8; 64];
+        b[..32].copy_from_slice(&self.r_b8.compress());
+        b[32..].copy_from_slice(&self.s.to_bytes());
+        b
+    }
+}
+
+/// circomlib's `unpackSignature`, see `decompress_point` and
+/// `Signature::new`. An `R8` that does not unpack is `InvalidSignature`.
+pub fn decompress_signature(b: &[u8; 64]) -> Result<Signature, Error> {
+    let mut r = [0u8; 32];
+    r.copy_from_slice(&b[..32]);
+    let r_b8 = decompress_point(r).map_err(|e| Error::InvalidSignature(format!("r8: {}", e)))?;
+    Signature::new(r_b8, &BigInt::from_bytes_le(Sign::Plus, &b[32..]))
+}
+
+/// `H(r, pk, m)`: circomlib's `poseidon([r.x, r.y, pk.x, pk.y, m])`, as a
+/// scalar. The parameters are fixed, whatever is registered for width 6.
+fn hash_message(r: &Point, pk: &Point, m: &Fr) -> Scalar {
+    let mut state = [Fr::zero(), r.x, r.y, pk.x, pk.y, *m];
+    Scalar::from_fr(&poseidon::hash(&EDDSA_PARAMS, &mut state))
+}
+
+/// `-8 * hm`, the scalar of `A` in the verification equation.
+fn minus_8hm(hm: &Scalar) -> Scalar {
+    let mut k = *hm;
+    k.double();
+    k.double();
+    k.double();
+    k.negate();
+    k
+}
+
+/// The checks `verify_poseidon` makes before the equation; `S` is in
+/// range by construction.
+fn check_points(pk: &Point, sig: &Signature, pk_in_subgroup: bool) -> Result<(), Rejection> {
+    if !pk.is_on_curve() {
+        return Err(Rejection::PublicKeyNotOnCurve);
+    }
+    if !pk_in_subgroup {
+        return Err(Rejection::PublicKeyNotInSubgroup);
+    }
+    let r = &sig.r_b8;
+    if !r.is_on_curve() {
+        return Err(Rejection::RNotOnCurve);
+    }
+    if !r.in_subgroup() {
+        return Err(Rejection::RNotInSubgroup);
+    }
+    Ok(())
+}
+
+/// `S * BASE8 - 8 * hm * A == R8`, with the two multiplications sharing
+/// their doublings; `A` is in the subgroup, so `-8 * hm` works mod `l`.
+fn equation_holds(pk: &Point, sig: &Signature, hm: &Scalar) -> bool {
+    let lhs = mul::double_mul(&sig.s.to_limbs(), &BASE8, &minus_8hm(hm).to_limbs(), pk);
+    lhs.equals(&sig.r_b8.extended())
+}
 
-    let mut hm_input = vec![r.x, r.y, pk.x, pk.y, m, Fr::zero()];
+/// circomlib's `verifyPoseidon`: `A` and `R8` must be points of the prime
+/// order subgroup, and
+/// `S * BASE8 == R8 + 8 * H(R8, A, m) * A`, see `hash_message`. A signature
+/// that does not verify fails with `Error::SignatureRejected` naming the
+/// first check it failed.
+pub fn verify_poseidon(pk: &Point, sig: &Signature, m: &Fr) -> Result<(), Error> {
+    check_points(pk, sig, pk.in_subgroup()).map_err(Error::SignatureRejected)?;
+    let hm = hash_message(&sig.r_b8, pk, m);
+    if !equation_holds(pk, sig, &hm) {
+        return Err(Error::SignatureRejected(Rejection::EquationMismatch));
+    }
+    Ok(())
+}
+
+/// `sum(scalars[i] * points[i])`, see `mul::pippenger`. Fails with
+/// `InvalidParams` unless there are as many scalars as points.
+pub fn multi_scalar_mul(points: &[Point], scalars: &[Scalar]) -> Result<Point, Error> {
+    if points.len() != scalars.len() {
+        return Err(Error::InvalidParams(format!(
+            "{} scalars for {} points", scalars.len(), points.len())));
+    }
+    let scalars: Vec<[u64; 4]> = scalars.iter().map(Scalar::to_limbs).collect();
+    Ok(mul::pippenger(points, &scalars).curve_affine())
+}
+
+/// Verifies `(pk, sig, m)` items in parallel with the same outcome as
+/// `verify_poseidon` on each, but checks the equations of all the items
+/// that pass the point checks at once: for random 128-bit `z_i`,
+/// `sum(z_i * (S_i * BASE8 - R8_i - 8 * hm_i * A_i))` must be the identity.
+/// Only when it is not are the equations checked one by one, to find the
+/// items that fail. The subgroup check of a public key signing several
+/// items is done once.
+pub fn verify_batch(items: &[(Point, Signature, Fr)]) -> Vec<Result<(), Rejection>> {
+    let pks: HashSet<&Point> = items.iter().map(|(pk, _, _)| pk).collect();
+    let pk_in_subgroup: HashMap<&Point, bool> = pks
+        .into_par_iter()
+        .map(|pk| (pk, pk.in_subgroup()))
+        .collect();
+    let hms = items
+        .par_iter()
+        .map(|(pk, sig, m)| {
+            check_points(pk, sig, pk_in_subgroup[pk]).map(|()| hash_message(&sig.r_b8, pk, m))
+        })
+        .collect::<Vec<Result<Scalar, Rejection>>>();
+
+    // every point is in the subgroup by now, so scalars work mod l.
+    let mut rng = rand::thread_rng();
+    let mut s_sum = Scalar::zero();
+    let mut points = vec![];
+    let mut scalars = vec![];
+    for ((pk, sig, _), hm) in items.iter().zip(&hms) {
+        if let Ok(hm) = hm {
+            let mut wide = [0u8; 64];
+            wide[..16].copy_from_slice(&rng.gen::<[u8; 16]>());
+            let z = Scalar::from_bytes_wide(&wide);
+            let mut zs = z;
+            zs.mul_assign(&sig.s);
+            s_sum.add_assign(&zs);
+            let mut minus_z = z;
+            minus_z.negate();
+            points.push(sig.r_b8.clone());
+            scalars.push(minus_z.to_limbs());
+            let mut zh = minus_8hm(hm);
+            zh.mul_assign(&z);
+            points.push(pk.clone());
+            scalars.push(zh.to_limbs());
+        }
+    }
+
+    // one Pippenger per thread, so the buckets still amortize.
+    let chunk = (points.len() / rayon::current_num_threads()).max(64);
+    let sum = points
+        .par_chunks(chunk)
+        .zip(scalars.par_chunks(chunk))
+        .map(|(p, s)| mul::pippenger(p, s))
+        .reduce(PointExtended::identity, |a, b| a.add(&b))
+        .add(&mul_base8_extended(&s_sum));
+    let all_hold = sum.is_identity();
+
+    items
+        .par_iter()
+        .zip(hms.into_par_iter())
+        .map(|((pk, sig, _), hm)| match hm {
+            Ok(hm) if all_hold || equation_holds(pk, sig, &hm) => Ok(()),
+            Ok(_) => Err(Rejection::EquationMismatch),
+            Err(r) => Err(r),
+        })
+        .collect()
+}
+
+/// `verify_poseidon` as a bool.
+pub fn verify(pk: &Point, sig: &Signature, m: &Fr) -> bool {
+    verify_poseidon(pk, sig, m).is_ok()
+}
+
+/// The check of the original `eddsa_verify` binding, kept for its callers:
+/// `S * B8 == R + hm * A`, over `B8` rather than `BASE8`, without the
+/// cofactor and without checking the subgroup or the range of `S`. `hm`
+/// is `poseidon::hash` of `[R.x, R.y, A.x, A.y, m, 0]` with the parameters
+/// registered for width 6. Signatures of circomlib, or of `sign_poseidon`,
+/// do not pass it; see `verify_poseidon`. Points off the curve fail it.
+#[deprecated(note = "malleable and not circomlib's check; use `verify_poseidon` or `verify_batch`")]
+pub fn verify_legacy(pk: &Point, r: &Point, s: &BigInt, m: &Fr) -> Result<bool, Error> {
+    if !pk.is_on_curve() || !r.is_on_curve() {
+        return Ok(false);
+    }
     let params_map = poseidon::POSEIDON_PARAMS.read().unwrap();
-    let params = params_map.get(&(hm_input.len()))
-        .expect("params t:{inp.len()} not initialized");
-    let hm = poseidon::hash(params, &mut hm_input);
-    let hm_b = BigInt::parse_bytes(to_hex(&hm).as_bytes(), 16).unwrap();
-
-    let lhs = B8.mul_scalar(&s);
-    let rhs = r
-        .projective()
-        .add(&pk.mul_scalar(&hm_b).projective());
-    // println!("lhs: {:#?}, rhs: {:#?}", lhs, rhs.affine());
-    lhs.equals(rhs.affine())
+    let params = params_map.get(&6).ok_or(Error::ParamsNotInitialized(6))?;
+    let hm = poseidon::hash(params, &mut [r.x, r.y, pk.x, pk.y, *m, Fr::zero()]);
+    let hm = BigInt::from(poseidon::fr_to_biguint(&hm));
+
+    let rhs = r.extended().add(&pk.mul_scalar_extended(&hm));
+    Ok(B8.mul_scalar_extended(s).equals(&rhs))
+}
+
+fn blake512(data: &[u8]) -> [u8; 64] {
+    let mut out = [0u8; 64];
+    out.copy_from_slice(&blake_hash::Blake512::digest(data));
+    out
+}
+
+/// An EdDSA private key: a 32-byte seed expanded with Blake-512 as in
+/// circomlib and go-iden3-crypto. The first half of the expansion, pruned,
+/// is the secret scalar and the second half seeds the nonces. Everything
+/// derived from the seed is computed in constant time, with `scalar::ct`
+/// and `ct`.
+#[derive(Clone)]
+pub struct PrivateKey {
+    key: [u8; 32],
+}
+
+impl PrivateKey {
+    pub fn import(key: [u8; 32]) -> PrivateKey {
+        PrivateKey { key }
+    }
+
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    /// The pruned scalar, a multiple of the cofactor 8 below `2^255`, mod
+    /// `l`.
+    fn pruned_scalar(&self) -> Scalar {
+        let mut s = self.scalar_key();
+        for _ in 0..3 {
+            s = scalar::ct::add(&s, &s);
+        }
+        s
+    }
+
+    fn pruned_bytes(&self) -> [u8; 32] {
+        let mut h = [0u8; 32];
+        h.copy_from_slice(&blake512(&self.key)[..32]);
+        h[0] &= 0xf8;
+        h[31] &= 0x7f;
+        h[31] |= 0x40;
+        h
+    }
+
+    /// The scalar of the public key, `pruned_scalar >> 3` mod `l`.
+    pub fn scalar_key(&self) -> Scalar {
+        scalar::ct::from_limbs(&self.scalar_limbs())
+    }
+
+    /// `scalar_key` as limbs, for `ct`, without going through `BigInt`.
+    fn scalar_limbs(&self) -> [u64; 4] {
+        let h = self.pruned_bytes();
+        let mut k = [0u64; 4];
+        for (i, l) in k.iter_mut().enumerate() {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(&h[8 * i..8 * i + 8]);
+            *l = u64::from_le_bytes(b);
+        }
+        for i in 0..4 {
+            k[i] = (k[i] >> 3) | k.get(i + 1).map_or(0, |l| l << 61);
+        }
+        k
+    }
+
+    /// `scalar_key * BASE8`, in constant time.
+    pub fn public(&self) -> Point {
+        ct::affine(&ct::mul_base8(&self.scalar_limbs()))
+    }
+
+    /// The deterministic nonce `r = Blake512(h[32..64] || m) mod l`, `m` as
+    /// 32 little-endian bytes.
+    fn nonce(&self, m: &Fr) -> Scalar {
+        let h = blake512(&self.key);
+        let mut r_input = [0u8; 64];
+        r_input[..32].copy_from_slice(&h[32..]);
+        poseidon::fr_to_bytes(m, false, &mut r_input[32..]);
+        scalar::ct::from_bytes_wide(&blake512(&r_input))
+    }
+
+    /// Signs `m` as circomlib's `signPoseidon`: `R8 = r * BASE8` and
+    /// `S = r + H(R8, A, m) * s mod l`, with `s` the pruned scalar, so
+    /// that `S * BASE8 == R8 + 8 * H(R8, A, m) * A`.
+    pub fn sign_poseidon(&self, m: &Fr) -> Signature {
+        let r = self.nonce(m);
+        let r_b8 = ct::affine(&ct::mul_base8(&r.to_limbs()));
+        let hm = hash_message(&r_b8, &self.public(), m);
+        let s = scalar::ct::add(&scalar::ct::mul(&hm, &self.pruned_scalar()), &r);
+        Signature { r_b8, s }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn point(x: &str, y: &str) -> Point {
+        Point { x: Fr::from_str(x).unwrap(), y: Fr::from_str(y).unwrap() }
+    }
+
+    // the key of circomlibjs' eddsa tests, 0001020304050607080900010203...
+    fn circomlib_key() -> PrivateKey {
+        let mut key = [0u8; 32];
+        key.iter_mut().enumerate().for_each(|(i, b)| *b = (i % 10) as u8);
+        PrivateKey::import(key)
+    }
+
+    #[test]
+    fn test_blake512() {
+        let h = blake512(b"");
+        assert_eq!(h[..8], [0xa8, 0xcf, 0xbb, 0xd7, 0x37, 0x26, 0x06, 0x2d]);
+        assert_eq!(h[56..], [0x80, 0xaf, 0x3f, 0xc7, 0x91, 0x3e, 0xf5, 0xb8]);
+    }
+
+    // circomlibjs' `signPoseidon` and `verifyPoseidon` of its eddsa
+    // tests, end to end.
+    #[test]
+    #[allow(deprecated)]
+    fn test_circomlib_signature() {
+        let key = circomlib_key();
+        let pk = point(
+            "13277427435165878497778222415993513565335242147425444199013288855685581939618",
+            "13622229784656158136036771217484571176836296686641868549125388198837476602820",
+        );
+        let sig = Signature {
+            r_b8: point(
+                "11384336176656855268977457483345535180380036354188103142384839473266348197733",
+                "15383486972088797283337779941324724402501462225528836549661220478783371668959",
+            ),
+            s: Scalar::from_str(
+                "1672775540645840396591609181675628451599263765380031905495115170613215233181",
+            ).unwrap(),
+        };
+        // leBuff2int(0x00010203040506070809), the message of those tests.
+        let m = Fr::from_str("42649378395939397566720").unwrap();
+
+        assert_eq!(key.public(), pk);
+        assert_eq!(key.sign_poseidon(&m), sig);
+        assert_eq!(verify_poseidon(&pk, &sig, &m), Ok(()));
+        assert_eq!(verify_batch(&[(pk.clone(), sig.clone(), m)]), vec![Ok(())]);
+
+        let mut other = m;
+        other.add_assign(&Fr::one());
+        assert!(!verify(&pk, &sig, &other));
+        assert!(!verify(&-&pk, &sig, &m));
+        // the circomlib signature is not one of the legacy check.
+        poseidon::register_circomlib_params();
+        assert_eq!(verify_legacy(&pk, &sig.r_b8, &sig.s.to_bigint(), &m), Ok(false));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_verify_legacy() {
+        poseidon::register_circomlib_params();
+        let mut rng = rand::thread_rng();
+        let k = rng.gen::<Scalar>().to_bigint();
+        let r = rng.gen::<Scalar>().to_bigint();
+        let pk = B8.mul_scalar(&k);
+        let r_b8 = B8.mul_scalar(&r);
+        let m: Fr = rng.gen();
+
+        let params = poseidon::registered_params(6).unwrap();
+        let hm = poseidon::hash(&params, &mut [r_b8.x, r_b8.y, pk.x, pk.y, m, Fr::zero()]);
+        let hm = BigInt::from(poseidon::fr_to_biguint(&hm));
+        let s = (&r + &hm * &k) % &*SUBORDER;
+        assert_eq!(verify_legacy(&pk, &r_b8, &s, &m), Ok(true));
+        assert_eq!(verify_legacy(&pk, &r_b8, &(&r + 8 * &hm * &k), &m), Ok(false));
+        let mut other = m;
+        other.add_assign(&Fr::one());
+        assert_eq!(verify_legacy(&pk, &r_b8, &s, &other), Ok(false));
+
+        // off the curve, where multiplying could reach Z = 0.
+        let zero = Point { x: Fr::zero(), y: Fr::zero() };
+        assert_eq!(verify_legacy(&zero, &r_b8, &s, &m), Ok(false));
+        assert_eq!(verify_legacy(&pk, &zero, &s, &m), Ok(false));
+        assert_eq!(verify_legacy(&zero, &Point::identity(), &BigInt::from(5), &Fr::one()), Ok(false));
+    }
+
+    fn add(p: &Point, q: &Point) -> Point {
+        p.projective().add(&q.projective()).affine().unwrap()
+    }
+
+    #[test]
+    fn test_sign_poseidon() {
+        let mut rng = rand::thread_rng();
+        let key = circomlib_key();
+        let pk = key.public();
+        let m: Fr = rng.gen();
+        let sig = key.sign_poseidon(&m);
+
+        let hm = hash_message(&sig.r_b8, &pk, &m);
+        let rhs = sig.r_b8.projective().add(&pk.mul_scalar(&(hm.to_bigint() * 8)).projective());
+        assert!(BASE8.mul_scalar(&sig.s.to_bigint()).equals(rhs.affine().unwrap()));
+        assert!(pk.equals(BASE8.mul_scalar(&key.scalar_key().to_bigint())));
+        assert_eq!(verify_poseidon(&pk, &sig, &m), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_rejections() {
+        let mut rng = rand::thread_rng();
+        let key = PrivateKey::import(rng.gen());
+        let pk = key.public();
+        let m: Fr = rng.gen();
+        let sig = key.sign_poseidon(&m);
+        let rejected = |pk: &Point, sig: &Signature, m: &Fr| {
+            match verify_poseidon(pk, sig, m) {
+                Err(Error::SignatureRejected(r)) => r,
+                other => panic!("not rejected: {:?}", other),
+            }
+        };
+
+        let mut other = m;
+        other.add_assign(&Fr::one());
+        assert_eq!(rejected(&pk, &sig, &other), Rejection::EquationMismatch);
+        assert!(!verify(&pk, &sig, &other));
+
+        // S + l acts as S on BASE8, so it cannot even make a signature.
+        let high = sig.s.to_bigint() + &*SUBORDER;
+        let out_of_range = Err(Error::SignatureRejected(Rejection::SOutOfRange));
+        assert_eq!(Signature::new(sig.r_b8.clone(), &high).map(|_| ()), out_of_range);
+        let mut packed = sig.compress();
+        packed[32..].copy_from_slice(&high.to_bytes_le().1);
+        assert_eq!(decompress_signature(&packed).map(|_| ()), out_of_range);
+
+        // (0, -1) has order 2, adding it leaves the point on the curve.
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let t2 = Point { x: Fr::zero(), y: minus_one };
+        assert_eq!(rejected(&add(&pk, &t2), &sig, &m), Rejection::PublicKeyNotInSubgroup);
+        let torsion_r = Signature { r_b8: add(&sig.r_b8, &t2), s: sig.s };
+        assert_eq!(rejected(&pk, &torsion_r, &m), Rejection::RNotInSubgroup);
+
+        let off_curve = Point { x: Fr::one(), y: Fr::one() };
+        assert_eq!(rejected(&off_curve, &sig, &m), Rejection::PublicKeyNotOnCurve);
+        let bad_r = Signature { r_b8: off_curve, s: sig.s };
+        assert_eq!(rejected(&pk, &bad_r, &m), Rejection::RNotOnCurve);
+    }
+
+    #[test]
+    fn test_extended_matches_projective() {
+        let mut rng = rand::thread_rng();
+        let points: Vec<Point> = (0..8).map(|_| BASE8.mul_scalar(&BigInt::from(rng.gen::<u64>()))).collect();
+        let mut extended = vec![];
+        for (p, q) in points.iter().zip(points.iter().skip(1)) {
+            let expected = add(p, q);
+            let sum = p.extended().add(&q.extended());
+            assert_eq!(sum.affine(), Some(expected.clone()));
+            assert_eq!(p.extended().double().affine(), Some(add(p, p)));
+            // a non-normalized left operand exercises Z1 != 1.
+            let doubled = p.extended().double();
+            assert!(doubled.add_mixed(&q.extended()).equals(&doubled.add(&q.extended())));
+            assert!(PointExtended::identity().add(&sum).equals(&sum));
+            extended.push(sum.double());
+        }
+
+        let affine = batch_affine(&extended);
+        for (e, a) in extended.iter().zip(&affine) {
+            assert_eq!(&e.affine(), a);
+            assert!(a.as_ref().unwrap().is_on_curve());
+        }
+        assert!(batch_normalize(&extended).iter().all(|p| p.z == Fr::one()));
+
+        // Z = 0 is not a point, and leaves the rest of the batch alone.
+        let mut infinity = extended[0].clone();
+        infinity.z = Fr::zero();
+        assert_eq!(infinity.affine(), None);
+        extended.insert(2, infinity);
+        let with_infinity = batch_affine(&extended);
+        assert_eq!(with_infinity[2], None);
+        assert_eq!(with_infinity[..2], affine[..2]);
+        assert_eq!(with_infinity[3..], affine[2..]);
+        assert!(BASE8.mul_scalar_extended(&SUBORDER).is_identity());
+    }
+
+    #[test]
+    fn test_mul_b8() {
+        let mut rng = rand::thread_rng();
+        let l = &*SUBORDER;
+        let mut scalars = vec![BigInt::from(0u8), BigInt::from(1u8), BigInt::from(15u8), l - 1];
+        scalars.extend((0..10).map(|_| BigInt::from_bytes_le(Sign::Plus, &rng.gen::<[u8; 32]>()) % l));
+        for n in &scalars {
+            assert!(mul_base8(&Scalar::from_bigint(n).unwrap()).equals(BASE8.mul_scalar(n)));
+        }
+    }
+
+    #[test]
+    fn test_multi_scalar_mul() {
+        let mut rng = rand::thread_rng();
+        let points: Vec<Point> = (0..5u64).map(|i| BASE8.mul_scalar(&BigInt::from(i + 2))).collect();
+        let scalars: Vec<Scalar> = (0..5).map(|_| rng.gen()).collect();
+        let expected = points.iter().zip(&scalars)
+            .fold(Point::identity().projective(), |acc, (p, s)| acc.add(&p.mul_scalar(&s.to_bigint()).projective()));
+        assert_eq!(multi_scalar_mul(&points, &scalars), Ok(expected.affine().unwrap()));
+        assert_eq!(multi_scalar_mul(&[], &[]), Ok(Point::identity()));
+        assert!(multi_scalar_mul(&points, &scalars[1..]).is_err());
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let mut rng = rand::thread_rng();
+        let mut items: Vec<(Point, Signature, Fr)> = (0..10)
+            .map(|_| {
+                let key = PrivateKey::import(rng.gen());
+                let m: Fr = rng.gen();
+                (key.public(), key.sign_poseidon(&m), m)
+            })
+            .collect();
+        assert!(verify_batch(&items).iter().all(Result::is_ok));
+
+        items[3].2.add_assign(&Fr::one());
+        items[7].1.s.add_assign(&Scalar::one());
+        // a key out of the subgroup, shared by two items, and a valid key
+        // signing twice.
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let torsion = &items[1].0 + &Point::new(Fr::zero(), minus_one).unwrap();
+        items[1].0 = torsion.clone();
+        items[5].0 = torsion;
+        let key = PrivateKey::import(rng.gen());
+        for _ in 0..2 {
+            let m: Fr = rng.gen();
+            items.push((key.public(), key.sign_poseidon(&m), m));
+        }
+        let results = verify_batch(&items);
+        for (i, (pk, sig, m)) in items.iter().enumerate() {
+            let single = verify_poseidon(pk, sig, m).map_err(|e| match e {
+                Error::SignatureRejected(r) => r,
+                e => panic!("{}", e),
+            });
+            assert_eq!(results[i], single);
+        }
+        assert_eq!(results[3], Err(Rejection::EquationMismatch));
+        assert_eq!(results[7], Err(Rejection::EquationMismatch));
+        assert_eq!(results[1], Err(Rejection::PublicKeyNotInSubgroup));
+        assert_eq!(results[5], Err(Rejection::PublicKeyNotInSubgroup));
+        assert_eq!(results[10..], [Ok(()), Ok(())]);
+        assert_eq!(verify_batch(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_point_api() {
+        let mut rng = rand::thread_rng();
+        let (a, b): (Scalar, Scalar) = (rng.gen(), rng.gen());
+        let p = &*BASE8 * &a;
+        let q = BASE8.clone() * b;
+        assert_eq!(p, BASE8.mul_scalar(&a.to_bigint()));
+
+        let mut sum = a;
+        sum.add_assign(&b);
+        assert_eq!(&p + &q, &*BASE8 * &sum);
+        assert_eq!(p.clone() - q.clone(), &p + &-&q);
+        assert!((&p - &p).is_identity());
+        assert_eq!(&p + &Point::identity(), p);
+        assert_eq!(-(-p.clone()), p);
+        let mut minus_a = a;
+        minus_a.negate();
+        assert_eq!(-&p, &*BASE8 * &minus_a);
+
+        let set: std::collections::HashSet<Point> = vec![p.clone(), q.clone(), p.clone()].into_iter().collect();
+        assert_eq!(set.len(), 2);
+
+        // (0, -1) has order 2: the cofactor clears it.
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let t2 = Point::new(Fr::zero(), minus_one).unwrap();
+        let torsion = &p + &t2;
+        assert!(torsion.is_on_curve() && !torsion.in_subgroup());
+        assert!(torsion.mul_by_cofactor().in_subgroup());
+        assert_eq!(torsion.mul_by_cofactor(), p.mul_scalar(&BigInt::from(8u8)));
+
+        assert!(Point::new(Fr::one(), Fr::one()).is_err());
+        // off the curve, where `l * (0, 0)` would reach Z = 0.
+        assert!(!Point { x: Fr::zero(), y: Fr::zero() }.in_subgroup());
+        assert_eq!((p.x(), p.y()), (p.x, p.y));
+        let infinity = PointProjective { x: Fr::one(), y: Fr::one(), z: Fr::zero() };
+        assert_eq!(infinity.affine(), None);
+    }
+
+    #[test]
+    fn test_compress_point() {
+        let pk = BASE8.mul_scalar(&circomlib_key().scalar_key().to_bigint());
+        // y = 13622...6602820 little-endian, x above (p - 1) / 2.
+        let packed = pk.compress();
+        assert_eq!(packed[..4], [0xc4, 0x33, 0xf7, 0xa6]);
+        assert_eq!(packed[28..], [0xa9, 0xe8, 0x1d, 0x9e]);
+        assert!(decompress_point(packed).unwrap().equals(pk));
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let p = BASE8.mul_scalar(&BigInt::from(rng.gen::<u64>()));
+            assert!(decompress_point(p.compress()).unwrap().equals(p.clone()));
+            let mut neg = p.clone();
+            neg.x.negate();
+            assert_ne!(neg.compress(), p.compress());
+            assert!(decompress_point(neg.compress()).unwrap().equals(neg));
+        }
+
+        // unpackPoint returns null for (0, 1) and (0, -1), sign bit or not.
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        for p in &[Point::identity(), Point { x: Fr::zero(), y: minus_one }] {
+            let mut packed = p.compress();
+            assert!(decompress_point(packed).is_err());
+            packed[31] |= 0x80;
+            assert!(decompress_point(packed).is_err());
+        }
+        assert!(decompress_point([0x7f; 32]).is_err());
+    }
+
+    #[test]
+    fn test_compress_signature() {
+        let mut rng = rand::thread_rng();
+        let s: Scalar = rng.gen();
+        let sig = Signature { r_b8: BASE8.mul_scalar(&BigInt::from(rng.gen::<u64>())), s };
+        let packed = sig.compress();
+        assert_eq!(packed[..32], sig.r_b8.compress());
+        assert_eq!(packed[32..], s.to_bytes());
+        let unpacked = decompress_signature(&packed).unwrap();
+        assert!(unpacked.r_b8.equals(sig.r_b8) && unpacked.s == sig.s);
+
+        let mut bad_r = packed;
+        bad_r[..32].copy_from_slice(&[0x7f; 32]);
+        assert!(matches!(decompress_signature(&bad_r), Err(Error::InvalidSignature(_))));
+    }
+
+    #[test]
+    fn test_circomlib_points_in_subgroup() {
+        let key = circomlib_key();
+        let m = Fr::from_str("42649378395939397566720").unwrap();
+        for p in &[
+            BASE8.mul_scalar(&key.scalar_key().to_bigint()),
+            BASE8.mul_scalar(&key.nonce(&m).to_bigint()),
+            BASE8.clone(),
+            B8.clone(),
+        ] {
+            assert!(p.is_on_curve() && p.in_subgroup());
+        }
+    }
 }
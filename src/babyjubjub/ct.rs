@@ -0,0 +1,163 @@
+// Constant-time scalar multiplication for secret scalars, private keys and
+// nonces. Scalars are read in fixed 4-bit windows, every table entry goes
+// through a masked select, and doublings use the complete addition, all
+// over the masked field operations of `poseidon::ct`.
+
+use super::{Point, PointExtended, A, BASE8_TABLE, D};
+use crate::poseidon::ct as fr;
+
+/// add-2008-hwcd, as `PointExtended::add`, which is complete on this curve
+/// and so also doubles without a branch.
+fn add(p: &PointExtended, q: &PointExtended) -> PointExtended {
+    let a = fr::mul(&p.x, &q.x);
+    let b = fr::mul(&p.y, &q.y);
+    let c = fr::mul(&fr::mul(&D, &p.t), &q.t);
+    let d = fr::mul(&p.z, &q.z);
+    let e = fr::mul(&fr::add(&p.x, &p.y), &fr::add(&q.x, &q.y));
+    let e = fr::sub(&fr::sub(&e, &a), &b);
+    let f = fr::sub(&d, &c);
+    let g = fr::add(&d, &c);
+    let h = fr::sub(&b, &fr::mul(&A, &a));
+    PointExtended {
+        x: fr::mul(&e, &f),
+        y: fr::mul(&g, &h),
+        t: fr::mul(&e, &h),
+        z: fr::mul(&f, &g),
+    }
+}
+
+/// `table[i]`, or the identity when `i` is out of range, reading every
+/// entry.
+fn lookup(table: &[PointExtended], i: u64) -> PointExtended {
+    let mut r = PointExtended::identity();
+    for (j, p) in table.iter().enumerate() {
+        let c = fr::eq_u64(j as u64, i);
+        r.x = fr::select(&r.x, &p.x, c);
+        r.y = fr::select(&r.y, &p.y, c);
+        r.t = fr::select(&r.t, &p.t, c);
+        r.z = fr::select(&r.z, &p.z, c);
+    }
+    r
+}
+
+/// The `w`-th 4-bit window of `k`.
+fn digit(k: &[u64; 4], w: usize) -> u64 {
+    (k[w / 16] >> (4 * (w % 16))) & 0x0f
+}
+
+/// `k * BASE8` from `BASE8_TABLE`: one lookup and one addition per window, the
+/// zero digit included.
+pub fn mul_base8(k: &[u64; 4]) -> PointExtended {
+    let mut r = PointExtended::identity();
+    for w in 0..64 {
+        let row = &BASE8_TABLE[15 * w..15 * (w + 1)];
+        r = add(&r, &lookup(row, digit(k, w).wrapping_sub(1)));
+    }
+    r
+}
+
+/// `k * p` with a fixed 4-bit window: four doublings, one lookup and one
+/// addition per window.
+pub fn mul(p: &Point, k: &[u64; 4]) -> PointExtended {
+    let p = p.extended();
+    let mut table = vec![p.clone()];
+    for i in 1..15 {
+        table.push(add(&table[i - 1], &p));
+    }
+    let mut r = PointExtended::identity();
+    for w in (0..64).rev() {
+        for _ in 0..4 {
+            r = add(&r, &r);
+        }
+        r = add(&r, &lookup(&table, digit(k, w).wrapping_sub(1)));
+    }
+    r
+}
+
+/// `PointExtended::affine` with the constant-time inversion.
+pub fn affine(p: &PointExtended) -> Point {
+    let zinv = fr::inverse(&p.z);
+    Point {
+        x: fr::mul(&p.x, &zinv),
+        y: fr::mul(&p.y, &zinv),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::babyjubjub::{mul::wnaf_mul, Scalar, BASE8, SUBORDER};
+    use num_bigint::{BigInt, Sign};
+    use rand::Rng;
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    fn random_limbs(rng: &mut impl Rng) -> [u64; 4] {
+        [rng.gen(), rng.gen(), rng.gen(), rng.gen()]
+    }
+
+    fn to_bigint(k: &[u64; 4]) -> BigInt {
+        let bytes: Vec<u8> = k.iter().flat_map(|l| l.to_le_bytes()).collect();
+        BigInt::from_bytes_le(Sign::Plus, &bytes)
+    }
+
+    #[test]
+    fn test_ct_mul() {
+        let mut rng = rand::thread_rng();
+        let p = BASE8.mul_scalar(&BigInt::from(rng.gen::<u64>()));
+        let l = Scalar::from_bigint(&(&*SUBORDER - 1u8)).unwrap().to_limbs();
+        for k in [[0; 4], [1, 0, 0, 0], [u64::MAX; 4], l, random_limbs(&mut rng)] {
+            let n = to_bigint(&k);
+            assert!(affine(&mul_base8(&k)).equals(BASE8.mul_scalar(&n)));
+            assert!(affine(&mul(&p, &k)).equals(p.mul_scalar(&n)));
+        }
+    }
+
+    /// dudect's test: Welch's t between the timings of a fixed scalar and
+    /// random ones, the classes interleaved at random and the slowest
+    /// tenth of the samples cropped. `|t| > 10` is a leak.
+    fn dudect_t(f: impl Fn(&[u64; 4]), samples: usize) -> f64 {
+        let mut rng = rand::thread_rng();
+        let inputs: Vec<(usize, [u64; 4])> = (0..samples)
+            .map(|_| match rng.gen::<bool>() {
+                false => (0, [0; 4]),
+                true => (1, random_limbs(&mut rng)),
+            })
+            .collect();
+        let mut times = Vec::with_capacity(samples);
+        for (class, k) in &inputs {
+            let start = Instant::now();
+            f(black_box(k));
+            times.push((*class, start.elapsed().as_nanos() as f64));
+        }
+
+        let mut sorted: Vec<f64> = times.iter().map(|t| t.1).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let crop = sorted[samples * 9 / 10];
+        let stats = |class| {
+            let xs: Vec<f64> = times.iter().filter(|t| t.0 == class && t.1 <= crop).map(|t| t.1).collect();
+            let n = xs.len() as f64;
+            let mean = xs.iter().sum::<f64>() / n;
+            let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            (mean, var, n)
+        };
+        let ((m0, v0, n0), (m1, v1, n1)) = (stats(0), stats(1));
+        (m0 - m1) / (v0 / n0 + v1 / n1).sqrt()
+    }
+
+    #[test]
+    fn test_ct_timing() {
+        let ct = |k: &[u64; 4]| {
+            black_box(mul_base8(k));
+        };
+        let t = dudect_t(ct, 2000);
+        assert!(t.abs() < 10.0, "constant-time mul_base8 leaks, t = {}", t);
+
+        // the variable-time path skips zero digits and must be caught.
+        let vt = |k: &[u64; 4]| {
+            black_box(wnaf_mul(&BASE8, k));
+        };
+        let t = dudect_t(vt, 2000);
+        assert!(t.abs() > 10.0, "variable-time mul_base8 not detected, t = {}", t);
+    }
+}
@@ -0,0 +1,258 @@
+// Variable-base scalar multiplication over native u64 limbs: wNAF for a
+// single point, Straus for two and Pippenger for many. Scalars are
+// non-negative, little-endian limbs, as `Scalar::to_limbs` returns them.
+
+use super::{batch_normalize, Point, PointExtended};
+
+/// wNAF window: 8 odd multiples per point.
+const W: u32 = 5;
+
+/// Width-`W` non-adjacent form, least significant digit first: every
+/// non-zero digit is odd, below `2^(W-1)` in absolute value, and followed
+/// by at least `W - 1` zeros.
+fn wnaf(k: &[u64]) -> Vec<i8> {
+    let mut k = k.to_vec();
+    k.push(0);
+    let mut out = Vec::with_capacity(64 * k.len());
+    while k.iter().any(|&l| l != 0) {
+        let mut d = 0i64;
+        if k[0] & 1 == 1 {
+            d = (k[0] & ((1 << W) - 1)) as i64;
+            if d >= 1 << (W - 1) {
+                d -= 1 << W;
+            }
+            if d > 0 {
+                sub_small(&mut k, d as u64);
+            } else {
+                add_small(&mut k, (-d) as u64);
+            }
+        }
+        out.push(d as i8);
+        shr1(&mut k);
+    }
+    out
+}
+
+fn add_small(k: &mut [u64], v: u64) {
+    let mut carry = v;
+    for l in k.iter_mut() {
+        let (s, c) = l.overflowing_add(carry);
+        *l = s;
+        carry = c as u64;
+        if carry == 0 {
+            break;
+        }
+    }
+}
+
+fn sub_small(k: &mut [u64], v: u64) {
+    let mut borrow = v;
+    for l in k.iter_mut() {
+        let (s, b) = l.overflowing_sub(borrow);
+        *l = s;
+        borrow = b as u64;
+        if borrow == 0 {
+            break;
+        }
+    }
+}
+
+fn shr1(k: &mut [u64]) {
+    for i in 0..k.len() {
+        let next = k.get(i + 1).map_or(0, |l| l << 63);
+        k[i] = (k[i] >> 1) | next;
+    }
+}
+
+/// `p, 3p, ..., (2^(W-1) - 1)p` with `Z = 1`.
+fn odd_multiples(p: &Point) -> Vec<PointExtended> {
+    let p = p.extended();
+    let p2 = p.double();
+    let mut table = vec![p];
+    for i in 1..1 << (W - 2) {
+        table.push(table[i - 1].add(&p2));
+    }
+    table
+}
+
+/// Adds the point of wNAF digit `d` to `r`, from the odd multiples table.
+fn add_digit(r: &PointExtended, table: &[PointExtended], d: i8) -> PointExtended {
+    let q = &table[(d.unsigned_abs() / 2) as usize];
+    if d > 0 {
+        r.add_mixed(q)
+    } else {
+        r.add_mixed(&q.neg())
+    }
+}
+
+/// `k * p` by wNAF: about one mixed addition per `W + 1` bits.
+pub fn wnaf_mul(p: &Point, k: &[u64]) -> PointExtended {
+    let table = batch_normalize(&odd_multiples(p));
+    let mut r = PointExtended::identity();
+    for &d in wnaf(k).iter().rev() {
+        r = r.double();
+        if d != 0 {
+            r = add_digit(&r, &table, d);
+        }
+    }
+    r
+}
+
+/// `a * p + b * q` by Straus' method: both wNAFs share one chain of
+/// doublings.
+pub fn double_mul(a: &[u64], p: &Point, b: &[u64], q: &Point) -> PointExtended {
+    let mut tables = odd_multiples(p);
+    tables.extend(odd_multiples(q));
+    let tables = batch_normalize(&tables);
+    let (tp, tq) = tables.split_at(1 << (W - 2));
+
+    let (wa, wb) = (wnaf(a), wnaf(b));
+    let mut r = PointExtended::identity();
+    for i in (0..wa.len().max(wb.len())).rev() {
+        r = r.double();
+        for (w, t) in [(&wa, tp), (&wb, tq)] {
+            match w.get(i) {
+                Some(&d) if d != 0 => r = add_digit(&r, t, d),
+                _ => {}
+            }
+        }
+    }
+    r
+}
+
+/// `c` bits of `k` from bit `pos` on.
+fn window(k: &[u64], pos: usize, c: usize) -> usize {
+    let (i, shift) = (pos / 64, pos % 64);
+    let mut v = k.get(i).map_or(0, |l| l >> shift);
+    if shift + c > 64 {
+        v |= k.get(i + 1).map_or(0, |l| l << (64 - shift));
+    }
+    (v & ((1 << c) - 1)) as usize
+}
+
+/// `sum(scalars[i] * points[i])` by Pippenger's bucket method: per `c`-bit
+/// window, points are added into the bucket of their digit and the buckets
+/// are summed with weights by a running sum, for about `n + 2^(c+1)`
+/// additions per window instead of `n` per bit.
+///
+/// Panics unless there are as many scalars as points.
+pub fn pippenger(points: &[Point], scalars: &[[u64; 4]]) -> PointExtended {
+    assert_eq!(points.len(), scalars.len(), "one scalar per point");
+    let points: Vec<PointExtended> = points.iter().map(Point::extended).collect();
+    let bits = scalars
+        .iter()
+        .filter_map(|k| k.iter().rposition(|&l| l != 0).map(|i| 64 * (i + 1) - k[i].leading_zeros() as usize))
+        .max()
+        .unwrap_or(0);
+    let c = match points.len() {
+        0..=3 => 2,
+        n => ((n as f64).ln().ceil() as usize).clamp(3, 16),
+    };
+
+    let mut r = PointExtended::identity();
+    for w in (0..bits.div_ceil(c)).rev() {
+        for _ in 0..c {
+            r = r.double();
+        }
+        let mut buckets = vec![PointExtended::identity(); (1 << c) - 1];
+        for (p, k) in points.iter().zip(scalars) {
+            let d = window(k, w * c, c);
+            if d != 0 {
+                buckets[d - 1] = buckets[d - 1].add_mixed(p);
+            }
+        }
+        // sum(d * bucket[d - 1]) as the sum of the running suffix sums.
+        let mut running = PointExtended::identity();
+        let mut sum = PointExtended::identity();
+        for b in buckets.iter().rev() {
+            running = running.add(b);
+            sum = sum.add(&running);
+        }
+        r = r.add(&sum);
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::babyjubjub::{test_bit, BASE8};
+    use num_bigint::{BigInt, BigUint};
+    use rand::Rng;
+
+    // double-and-add with the projective formulas, independent of the above.
+    fn naive(p: &Point, n: &BigUint) -> Point {
+        let b = n.to_bytes_le();
+        let mut r = Point::identity().projective();
+        let mut exp = p.projective();
+        for i in 0..n.bits() as usize {
+            if test_bit(&b, i) {
+                r = r.add(&exp);
+            }
+            exp = exp.add(&exp);
+        }
+        r.affine().unwrap()
+    }
+
+    fn random_limbs(rng: &mut impl Rng, n: usize) -> Vec<u64> {
+        (0..n).map(|_| rng.gen()).collect()
+    }
+
+    fn to_biguint(k: &[u64]) -> BigUint {
+        let b: Vec<u8> = k.iter().flat_map(|l| l.to_le_bytes()).collect();
+        BigUint::from_bytes_le(&b)
+    }
+
+    #[test]
+    fn test_wnaf_digits() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let k = random_limbs(&mut rng, 4);
+            let digits = wnaf(&k);
+            let mut m = BigInt::from(0u8);
+            for &d in digits.iter().rev() {
+                m = (m << 1) + d;
+            }
+            assert_eq!(m, to_biguint(&k).into());
+            for (i, &d) in digits.iter().enumerate() {
+                if d != 0 {
+                    assert!(d % 2 != 0 && d.unsigned_abs() < 1 << (W - 1));
+                    assert!(digits[i + 1..].iter().take(W as usize - 1).all(|&z| z == 0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wnaf_mul_and_double_mul() {
+        let mut rng = rand::thread_rng();
+        let p = naive(&BASE8, &BigUint::from(rng.gen::<u64>()));
+        let q = naive(&BASE8, &BigUint::from(rng.gen::<u64>()));
+        for n in &[0, 1, 4, 5] {
+            let a = random_limbs(&mut rng, *n);
+            let b = random_limbs(&mut rng, 4);
+            let (na, nb) = (to_biguint(&a), to_biguint(&b));
+            assert_eq!(wnaf_mul(&p, &a).affine(), Some(naive(&p, &na)));
+            let expected = naive(&p, &na).extended().add(&naive(&q, &nb).extended());
+            assert!(double_mul(&a, &p, &b, &q).equals(&expected));
+        }
+    }
+
+    #[test]
+    fn test_pippenger() {
+        let mut rng = rand::thread_rng();
+        for n in &[0, 1, 3, 40] {
+            let points: Vec<Point> = (0..*n).map(|_| naive(&BASE8, &BigUint::from(rng.gen::<u64>()))).collect();
+            let scalars: Vec<[u64; 4]> = (0..*n).map(|_| rng.gen()).collect();
+            let expected = points.iter().zip(&scalars)
+                .fold(PointExtended::identity(), |acc, (p, s)| acc.add(&naive(p, &to_biguint(s)).extended()));
+            assert!(pippenger(&points, &scalars).equals(&expected));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "one scalar per point")]
+    fn test_pippenger_length_mismatch() {
+        pippenger(std::slice::from_ref(&BASE8), &[[1, 0, 0, 0], [2, 0, 0, 0]]);
+    }
+}
@@ -0,0 +1,117 @@
+// The scalar field of BabyJubJub's prime order subgroup: integers mod
+// `l = SUBORDER`, for keys, nonces, `S` and the hash of signed messages.
+// Arithmetic on secret scalars goes through `ct`.
+
+// the `PrimeField` derive generates a mont_reduce with one argument per limb.
+#![allow(clippy::too_many_arguments)]
+
+use ff::*;
+use num_bigint::{BigInt, Sign};
+
+use super::Fr;
+use crate::error::Error;
+use crate::poseidon;
+
+pub mod ct;
+
+#[derive(PrimeField)]
+#[PrimeFieldModulus = "2736030358979909402780800718157159386076813972158567259200215660948447373041"]
+#[PrimeFieldGenerator = "31"]
+pub struct Scalar(ScalarRepr);
+
+impl Scalar {
+    /// Reads 32 little-endian bytes, which must encode an integer below
+    /// `l`.
+    pub fn from_bytes(b: &[u8; 32]) -> Result<Scalar, Error> {
+        let mut repr = ScalarRepr::default();
+        repr.read_le(&b[..]).unwrap();
+        Scalar::from_repr(repr).map_err(|_| Error::InvalidFieldElement(
+            format!("{} is not below the subgroup order", BigInt::from_bytes_le(Sign::Plus, b))))
+    }
+
+    /// Reduces 64 little-endian bytes mod `l`, for scalars drawn from a
+    /// 512-bit hash with a negligible bias. Variable time, see
+    /// `ct::from_bytes_wide` for secret bytes.
+    pub fn from_bytes_wide(b: &[u8; 64]) -> Scalar {
+        // 2^64 mod l, by which the accumulator is shifted one limb a step.
+        let mut shift = Scalar::from_repr(ScalarRepr::from(1 << 32)).unwrap();
+        shift.square();
+        let mut acc = Scalar::zero();
+        for limb in b.chunks(8).rev() {
+            let mut l = [0u8; 8];
+            l.copy_from_slice(limb);
+            acc.mul_assign(&shift);
+            acc.add_assign(&Scalar::from_repr(ScalarRepr::from(u64::from_le_bytes(l))).unwrap());
+        }
+        acc
+    }
+
+    /// `n mod l` for an element of the base field, for `H(R8, A, m)`.
+    pub fn from_fr(n: &Fr) -> Scalar {
+        let mut b = [0u8; 64];
+        poseidon::fr_to_bytes(n, false, &mut b[..32]);
+        Scalar::from_bytes_wide(&b)
+    }
+
+    /// `n`, which must be in `0..l`.
+    pub fn from_bigint(n: &BigInt) -> Result<Scalar, Error> {
+        let (sign, b) = n.to_bytes_le();
+        if sign == Sign::Minus || b.len() > 32 {
+            return Err(Error::InvalidFieldElement(format!("{} is not below the subgroup order", n)));
+        }
+        let mut bb = [0u8; 32];
+        bb[..b.len()].copy_from_slice(&b);
+        Scalar::from_bytes(&bb)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut b = [0u8; 32];
+        self.into_repr().write_le(&mut b[..]).unwrap();
+        b
+    }
+
+    pub fn to_bigint(&self) -> BigInt {
+        BigInt::from_bytes_le(Sign::Plus, &self.to_bytes())
+    }
+
+    /// The canonical little-endian limbs, as `ct` takes them.
+    pub fn to_limbs(&self) -> [u64; 4] {
+        self.into_repr().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::babyjubjub::SUBORDER;
+    use rand::Rng;
+
+    #[test]
+    fn test_scalar_conversions() {
+        let l = &*SUBORDER;
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let mut wide = [0u8; 64];
+            rng.fill_bytes(&mut wide);
+            let s = Scalar::from_bytes_wide(&wide);
+            assert_eq!(s.to_bigint(), BigInt::from_bytes_le(Sign::Plus, &wide) % l);
+            assert_eq!(Scalar::from_bytes(&s.to_bytes()), Ok(s));
+            assert_eq!(Scalar::from_bigint(&s.to_bigint()), Ok(s));
+            let limbs: Vec<u8> = s.to_limbs().iter().flat_map(|l| l.to_le_bytes()).collect();
+            assert_eq!(BigInt::from_bytes_le(Sign::Plus, &limbs), s.to_bigint());
+
+            let n: Fr = rng.gen();
+            let expected = BigInt::from(poseidon::fr_to_biguint(&n)) % l;
+            assert_eq!(Scalar::from_fr(&n).to_bigint(), expected);
+        }
+
+        let mut minus_one = Scalar::one();
+        minus_one.negate();
+        assert_eq!(minus_one.to_bigint(), l - 1);
+        assert!(Scalar::from_bigint(l).is_err());
+        assert!(Scalar::from_bigint(&BigInt::from(-1)).is_err());
+        assert!(Scalar::from_bytes(&[0xff; 32]).is_err());
+        assert_eq!(Scalar::from_bytes_wide(&[0xff; 64]).to_bigint(),
+                   ((BigInt::from(1u8) << 512) - 1) % l);
+    }
+}
@@ -0,0 +1,79 @@
+// Constant-time `Scalar` arithmetic for the secret scalars of keys, nonces
+// and signatures, over the masked limb operations of `poseidon::ct`. The
+// `ff` derive's own operations branch on their values.
+
+use super::{Scalar, ScalarRepr, INV, MODULUS, R2};
+use crate::poseidon::ct::{add_mod, mont_mul};
+
+pub fn add(a: &Scalar, b: &Scalar) -> Scalar {
+    Scalar(ScalarRepr(add_mod(&(a.0).0, &(b.0).0, &MODULUS.0)))
+}
+
+pub fn mul(a: &Scalar, b: &Scalar) -> Scalar {
+    Scalar(ScalarRepr(mont_mul(&(a.0).0, &(b.0).0, &MODULUS.0, INV)))
+}
+
+/// `k mod l` for any 256-bit `k`, as little-endian limbs.
+pub fn from_limbs(k: &[u64; 4]) -> Scalar {
+    // k * R^2 / R = k * R, the Montgomery form of k.
+    Scalar(ScalarRepr(mont_mul(&R2.0, k, &MODULUS.0, INV)))
+}
+
+/// `Scalar::from_bytes_wide`: `lo + hi * 2^256 mod l` for the two halves
+/// of `b`.
+pub fn from_bytes_wide(b: &[u8; 64]) -> Scalar {
+    let mut limbs = [[0u64; 4]; 2];
+    for (l, c) in limbs.iter_mut().flatten().zip(b.chunks(8)) {
+        let mut le = [0u8; 8];
+        le.copy_from_slice(c);
+        *l = u64::from_le_bytes(le);
+    }
+    let [lo, hi] = limbs;
+    // hi * R^3 / R = hi * 2^256 * R, the Montgomery form of hi * 2^256.
+    let r3 = mont_mul(&R2.0, &R2.0, &MODULUS.0, INV);
+    add(&from_limbs(&lo), &Scalar(ScalarRepr(mont_mul(&r3, &hi, &MODULUS.0, INV))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use rand::Rng;
+
+    #[test]
+    fn test_ct_matches_field() {
+        let mut rng = rand::thread_rng();
+        let mut l_minus_one = Scalar::one();
+        l_minus_one.negate();
+        let mut samples: Vec<Scalar> = (0..500).map(|_| rng.gen()).collect();
+        samples.extend_from_slice(&[Scalar::zero(), Scalar::one(), l_minus_one]);
+
+        for (a, b) in samples.iter().zip(samples.iter().rev()) {
+            let mut e = *a;
+            e.add_assign(b);
+            assert_eq!(add(a, b), e);
+            let mut e = *a;
+            e.mul_assign(b);
+            assert_eq!(mul(a, b), e);
+        }
+
+        let mut wides = vec![[0u8; 64], [0xff; 64]];
+        wides.extend((0..100).map(|_| {
+            let mut b = [0u8; 64];
+            rng.fill_bytes(&mut b);
+            b
+        }));
+        for b in &wides {
+            assert_eq!(from_bytes_wide(b), Scalar::from_bytes_wide(b));
+            let mut k = [0u64; 4];
+            for (k, c) in k.iter_mut().zip(b.chunks(8)) {
+                let mut le = [0u8; 8];
+                le.copy_from_slice(c);
+                *k = u64::from_le_bytes(le);
+            }
+            let mut low = [0u8; 64];
+            low[..32].copy_from_slice(&b[..32]);
+            assert_eq!(from_limbs(&k), Scalar::from_bytes_wide(&low));
+        }
+    }
+}
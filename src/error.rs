@@ -0,0 +1,72 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No parameters registered for this width.
+    ParamsNotInitialized(usize),
+    /// A string that does not parse as a field element, or an integer out
+    /// of the range of `babyjubjub::Scalar`.
+    InvalidFieldElement(String),
+    /// Requested outputs outside `1..=t` for a width `t` permutation.
+    InvalidOutputCount(usize, usize),
+    /// Poseidon parameters, or a parameter of a call, that cannot be used.
+    InvalidParams(String),
+    /// Coordinates that are not a point of the curve.
+    InvalidPoint(String),
+    /// A malformed signature.
+    InvalidSignature(String),
+    /// A well-formed signature that does not verify.
+    SignatureRejected(Rejection),
+}
+
+/// Why `babyjubjub::verify_poseidon` rejected a signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// `S` is not below the subgroup order, found as the signature is read,
+    /// see `babyjubjub::Signature::new`.
+    SOutOfRange,
+    PublicKeyNotOnCurve,
+    /// The public key has a component of small order.
+    PublicKeyNotInSubgroup,
+    RNotOnCurve,
+    RNotInSubgroup,
+    /// `S * B8 != R8 + 8 * hm * A`.
+    EquationMismatch,
+}
+
+impl Rejection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rejection::SOutOfRange => "s_out_of_range",
+            Rejection::PublicKeyNotOnCurve => "public_key_not_on_curve",
+            Rejection::PublicKeyNotInSubgroup => "public_key_not_in_subgroup",
+            Rejection::RNotOnCurve => "r_not_on_curve",
+            Rejection::RNotInSubgroup => "r_not_in_subgroup",
+            Rejection::EquationMismatch => "equation_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name().replace('_', " "))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ParamsNotInitialized(t) => write!(f, "params t:{} not initialized", t),
+            Error::InvalidFieldElement(s) => write!(f, "invalid field element: {:?}", s),
+            Error::InvalidOutputCount(n, t) => {
+                write!(f, "cannot return {} outputs from a width {} state", n, t)
+            }
+            Error::InvalidParams(s) => write!(f, "invalid params: {}", s),
+            Error::InvalidPoint(s) => write!(f, "invalid point: {}", s),
+            Error::InvalidSignature(s) => write!(f, "invalid signature: {}", s),
+            Error::SignatureRejected(r) => write!(f, "signature rejected: {}", r),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -4,22 +4,50 @@ extern crate num_bigint;
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+mod constants;
+pub mod error;
 pub mod poseidon;
 pub mod babyjubjub;
+#[cfg(feature = "python")]
+pub mod python;
 
+#[cfg(feature = "python")]
 #[pymodule]
-fn crypto_rs(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn crypto_rs(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    python::add_exceptions(py, m)?;
+
+    m.add_class::<python::PyFr>()?;
+    m.add_class::<python::PyPoseidonParams>()?;
+    m.add_class::<python::PyPoseidon>()?;
+    m.add_class::<python::PyPoint>()?;
+    m.add_class::<python::PyPrivateKey>()?;
+    m.add_class::<python::PyPublicKey>()?;
+    m.add_class::<python::PySignature>()?;
+
+    m.add_function(wrap_pyfunction!(
+            python::poseidon_params, m)?)?;
+    m.add_function(wrap_pyfunction!(
+            python::poseidon_hash, m)?)?;
     m.add_function(wrap_pyfunction!(
-            poseidon::poseidon_params, m)?)?;
+            python::py_poseidon, m)?)?;
     m.add_function(wrap_pyfunction!(
-            poseidon::poseidon_hash, m)?)?;
+            python::py_poseidon_ex, m)?)?;
     m.add_function(wrap_pyfunction!(
-            poseidon::multi_poseidon_hash, m)?)?;
+            python::multi_poseidon_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(
+            python::batch_poseidon_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(
+            python::multi_poseidon_hash_limbs, m)?)?;
+    m.add_function(wrap_pyfunction!(
+            python::multi_poseidon_hash_bytes, m)?)?;
 
     m.add_function(wrap_pyfunction!(
-            babyjubjub::eddsa_verify, m)?)?;
+            python::eddsa_verify, m)?)?;
+    m.add_function(wrap_pyfunction!(
+            python::eddsa_verify_batch, m)?)?;
 
     // Python::with_gil(|py| -> PyResult<()> {
     //     let fast = PyModule::new(py, "fast")?;
@@ -1,16 +1,26 @@
+// the `PrimeField` derive generates a mont_reduce with one argument per limb.
+#![allow(clippy::too_many_arguments)]
+
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 
 use ff::*;
-use pyo3::prelude::*;
+use num_bigint::{BigInt, BigUint, Sign};
 use rayon::prelude::*;
 
+use crate::constants;
+use crate::error::Error;
+
+pub mod ct;
+pub mod kernel;
+
 #[derive(PrimeField)]
 #[PrimeFieldModulus = "21888242871839275222246405745257275088548364400416034343698204186575808495617"]
 #[PrimeFieldGenerator = "7"]
 pub struct Fr(FrRepr);
 
 // #[derive(Debug)]
+#[derive(Clone)]
 pub struct Constants {
     pub c: Vec<Fr>,
     pub m: Vec<Vec<Fr>>,
@@ -25,45 +35,197 @@ pub struct Constants {
 
 lazy_static! {
     pub static ref POSEIDON_PARAMS: RwLock<HashMap<usize, Constants>> = RwLock::new(HashMap::new());
+    static ref THREAD_POOLS: Mutex<HashMap<usize, Arc<rayon::ThreadPool>>> = Mutex::new(HashMap::new());
 }
 
-#[pyfunction]
-pub fn poseidon_params(
-    t: usize, n_rounds_f: usize, n_rounds_p: usize,
-    c: Vec<String>, m: Vec<Vec<String>>,) -> usize {
-    let params = Constants {
-        c: c.iter().map(|c| Fr::from_str(c).unwrap()).collect(),
-        m: m.iter().map(|l| 
-            l.iter().map(|m| Fr::from_str(m).unwrap()).collect()
-        ).collect(),
-        width: t,
-        n_rounds_f, n_rounds_p,
-        round1: n_rounds_f / 2,
-        round2: n_rounds_f / 2 + n_rounds_p,
-        round3: n_rounds_f + n_rounds_p,
-    };
+/// Runs `op` on a rayon pool of `threads` workers, or on the global pool
+/// when `None`. Pools are built once per size and reused across calls.
+pub fn install<R, OP>(threads: Option<usize>, op: OP) -> Result<R, Error>
+where
+    R: Send,
+    OP: FnOnce() -> R + Send,
+{
+    match threads {
+        None | Some(0) => Ok(op()),
+        Some(n) => {
+            let mut pools = THREAD_POOLS.lock().unwrap();
+            let pool = match pools.get(&n) {
+                Some(pool) => pool.clone(),
+                None => {
+                    let pool = rayon::ThreadPoolBuilder::new()
+                        .num_threads(n)
+                        .build()
+                        .map_err(|e| Error::InvalidParams(
+                            format!("cannot start {} threads: {}", n, e)))?;
+                    pools.entry(n).or_insert(Arc::new(pool)).clone()
+                }
+            };
+            drop(pools);
+            Ok(pool.install(op))
+        }
+    }
+}
+
+impl Constants {
+    /// Checks the shapes of `c` and `m` against the width and rounds, so
+    /// registered parameters never make `hash` index out of bounds. `c`
+    /// holds either one constant per round, added to every element, or one
+    /// per element of each round, as circomlib lays them out.
+    pub fn new(
+        t: usize, n_rounds_f: usize, n_rounds_p: usize,
+        c: Vec<Fr>, m: Vec<Vec<Fr>>) -> Result<Constants, Error> {
+        if t < 2 {
+            return Err(Error::InvalidParams(format!("width {} is below 2", t)));
+        }
+        let overflow = || Error::InvalidParams(format!(
+            "{} + {} rounds of width {} overflow", n_rounds_f, n_rounds_p, t));
+        let rounds = n_rounds_f.checked_add(n_rounds_p).ok_or_else(overflow)?;
+        let per_element = rounds.checked_mul(t).ok_or_else(overflow)?;
+        if c.len() != rounds && c.len() != per_element {
+            return Err(Error::InvalidParams(format!(
+                "{} round constants for {} rounds of width {}", c.len(), rounds, t)));
+        }
+        if m.len() != t || m.iter().any(|l| l.len() != t) {
+            return Err(Error::InvalidParams(format!("mds matrix is not {}x{}", t, t)));
+        }
 
-    let mut params_map = POSEIDON_PARAMS.write().unwrap();
-    params_map.insert(t, params);
+        Ok(Constants {
+            c,
+            m,
+            width: t,
+            n_rounds_f, n_rounds_p,
+            round1: n_rounds_f / 2,
+            round2: n_rounds_f / 2 + n_rounds_p,
+            round3: rounds,
+        })
+    }
+
+    /// Compact encoding: `t`, `n_rounds_f` and `n_rounds_p` as little-endian
+    /// u32s, then `c` and the rows of `m` as 32-byte little-endian elements.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let n = self.c.len() + self.width * self.width;
+        let mut out = Vec::with_capacity(12 + 32 * n);
+        for v in &[self.width, self.n_rounds_f, self.n_rounds_p] {
+            out.extend_from_slice(&(*v as u32).to_le_bytes());
+        }
+        write_frs(&mut out, self.c.iter().chain(self.m.iter().flatten()));
+        out
+    }
+
+    /// Inverse of `to_bytes`, validating the shapes like `new`.
+    pub fn from_bytes(b: &[u8]) -> Result<Constants, Error> {
+        let mut r = Reader(b);
+        let t = r.u32()?;
+        let n_rounds_f = r.u32()?;
+        let n_rounds_p = r.u32()?;
+        // `c` takes whatever `m` leaves, so both layouts round-trip.
+        let n_c = (r.0.len() / 32).checked_sub(t.saturating_mul(t))
+            .ok_or_else(|| Error::InvalidParams("truncated encoding".to_string()))?;
+        let c = r.frs(n_c)?;
+        let m = (0..t).map(|_| r.frs(t)).collect::<Result<_, _>>()?;
+        r.finish()?;
+        Constants::new(t, n_rounds_f, n_rounds_p, c, m)
+    }
+}
+
+fn write_frs<'a>(out: &mut Vec<u8>, frs: impl Iterator<Item = &'a Fr>) {
+    for f in frs {
+        let at = out.len();
+        out.resize(at + 32, 0);
+        fr_to_bytes(f, false, &mut out[at..]);
+    }
+}
+
+/// Cursor over the encodings written by `Constants::to_bytes` and
+/// `Sponge::to_bytes`.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < n {
+            return Err(Error::InvalidParams("truncated encoding".to_string()));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<usize, Error> {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(b) as usize)
+    }
+
+    fn frs(&mut self, n: usize) -> Result<Vec<Fr>, Error> {
+        // Bounds the allocation by the input length before trusting `n`.
+        let b = self.take(n.saturating_mul(32))?;
+        b.chunks(32).map(|f| fr_from_bytes(f, false)).collect()
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        if !self.0.is_empty() {
+            return Err(Error::InvalidParams(format!("{} trailing bytes", self.0.len())));
+        }
+        Ok(())
+    }
+}
 
-    &params_map[&t] as *const Constants as usize
+/// Registers `params` for their width, replacing any earlier set, so
+/// `poseidon`, `poseidon_ex`, `hash_items` and the Python bindings use it.
+pub fn register_params(params: Constants) {
+    POSEIDON_PARAMS.write().unwrap().insert(params.width, params);
+}
+
+/// A copy of the parameters registered for width `t`.
+pub fn registered_params(t: usize) -> Result<Constants, Error> {
+    POSEIDON_PARAMS.read().unwrap()
+        .get(&t)
+        .cloned()
+        .ok_or(Error::ParamsNotInitialized(t))
+}
+
+/// circomlib's parameters for width `t`, from `constants.rs`, which
+/// bundles widths 2 to 9.
+pub fn circomlib_params(t: usize) -> Result<Constants, Error> {
+    const N_ROUNDS_P: [usize; 8] = [56, 57, 56, 60, 60, 63, 64, 63];
+    if !(2..2 + N_ROUNDS_P.len()).contains(&t) {
+        return Err(Error::InvalidParams(format!("no circomlib parameters for width {}", t)));
+    }
+    let (c, m) = constants::constants();
+    let frs = |v: &[&str]| v.iter().map(|s| parse_fr(s)).collect::<Result<Vec<Fr>, Error>>();
+    Constants::new(
+        t, 8, N_ROUNDS_P[t - 2],
+        frs(&c[t - 2])?,
+        m[t - 2].iter().map(|l| frs(l)).collect::<Result<_, _>>()?,
+    )
+}
+
+/// The constant round `i` adds to element `j`.
+#[inline(always)]
+fn round_constant(params: &Constants, i: usize, j: usize) -> &Fr {
+    if params.c.len() == params.round3 {
+        &params.c[i]
+    } else {
+        &params.c[i * params.width + j]
+    }
 }
 
 pub fn sbox(params: &Constants, state: &mut [Fr], aux: &mut [Fr], i: usize) {
+    let k = kernel::kernel();
     if i < params.round1 || i >= params.round2 {
         state.iter_mut()
             .zip(aux.iter_mut())
             .for_each(|(s, a)| {
                 *a = *s;
-                s.square();
-                s.square();
-                s.mul_assign(a);
+                k.square(s);
+                k.square(s);
+                k.mul(s, a);
             })
     } else {
         aux[0] = state[0];
-        state[0].square();
-        state[0].square();
-        state[0].mul_assign(&aux[0]);
+        k.square(&mut state[0]);
+        k.square(&mut state[0]);
+        k.mul(&mut state[0], &aux[0]);
     }
 }
 
@@ -87,54 +249,820 @@ pub fn mix(params: &Constants, state: &mut [Fr], aux: &mut [Fr], res: &mut [Fr])
     state.copy_from_slice(res);
 }
 
+#[inline(always)]
+fn mac(a: u64, b: u64, c: u64, carry: &mut u64) -> u64 {
+    let t = (a as u128) + (b as u128) * (c as u128) + (*carry as u128);
+    *carry = (t >> 64) as u64;
+    t as u64
+}
+
+#[inline(always)]
+fn adc(a: u64, b: u64, carry: &mut u64) -> u64 {
+    let t = (a as u128) + (b as u128) + (*carry as u128);
+    *carry = (t >> 64) as u64;
+    t as u64
+}
+
+#[inline(always)]
+fn sbb(a: u64, b: u64, borrow: &mut u64) -> u64 {
+    let t = (1u128 << 64) + (a as u128) - (b as u128) - (*borrow as u128);
+    // branch-free, `ct` relies on it.
+    *borrow = 1 - (t >> 64) as u64;
+    t as u64
+}
+
+#[inline(always)]
+fn geq_modulus(r: &[u64]) -> bool {
+    // r holds at least four limbs, anything above them makes it larger.
+    if r[4..].iter().any(|l| *l != 0) {
+        return true;
+    }
+    for i in (0..4).rev() {
+        if r[i] != MODULUS.0[i] {
+            return r[i] > MODULUS.0[i];
+        }
+    }
+    true
+}
+
+#[inline(always)]
+fn sub_modulus(r: &mut [u64]) {
+    let mut borrow = 0;
+    for (l, m) in r.iter_mut().zip(MODULUS.0.iter().chain(std::iter::repeat(&0))) {
+        *l = sbb(*l, *m, &mut borrow);
+    }
+}
+
+/// Adds the unreduced 512-bit product `a * b` into the wide accumulator.
+#[inline(always)]
+fn mac_wide(acc: &mut [u64; 9], a: &[u64; 4], b: &[u64; 4]) {
+    for i in 0..4 {
+        let mut carry = 0;
+        for j in 0..4 {
+            acc[i + j] = mac(acc[i + j], a[i], b[j], &mut carry);
+        }
+        for l in acc[i + 4..].iter_mut() {
+            if carry == 0 {
+                break;
+            }
+            *l = adc(*l, 0, &mut carry);
+        }
+    }
+}
+
+/// Montgomery-reduces a sum of products of Montgomery-form elements, using
+/// the `MODULUS` and `INV` constants generated by the `PrimeField` derive.
+///
+/// The upper half is first brought below the modulus, so the accumulator
+/// is `< p * 2^256` as REDC requires, whatever the matrix width.
+fn reduce_wide(mut acc: [u64; 9]) -> Fr {
+    while geq_modulus(&acc[4..]) {
+        sub_modulus(&mut acc[4..]);
+    }
+
+    for i in 0..4 {
+        let k = acc[i].wrapping_mul(INV);
+        let mut carry = 0;
+        for j in 0..4 {
+            acc[i + j] = mac(acc[i + j], k, MODULUS.0[j], &mut carry);
+        }
+        for l in acc[i + 4..].iter_mut() {
+            if carry == 0 {
+                break;
+            }
+            *l = adc(*l, 0, &mut carry);
+        }
+    }
+
+    if geq_modulus(&acc[4..]) {
+        sub_modulus(&mut acc[4..]);
+    }
+
+    Fr(FrRepr([acc[4], acc[5], acc[6], acc[7]]))
+}
+
+/// MDS multiplication with lazy reduction: each row accumulates the wide
+/// products `m[i][j] * state[j]` and is reduced once, instead of paying a
+/// Montgomery reduction per `mul_assign`. Equal to `mix`.
+pub fn mix_lazy(params: &Constants, state: &mut [Fr], res: &mut [Fr]) {
+    params.m
+        .iter()
+        .zip(res.iter_mut())
+        .for_each(|(ml, res)| {
+            let mut acc = [0u64; 9];
+            ml.iter()
+                .zip(state.iter())
+                .for_each(|(m, s)| mac_wide(&mut acc, &(m.0).0, &(s.0).0));
+            *res = reduce_wide(acc);
+        });
+
+    state.copy_from_slice(res);
+}
+
 pub fn hash(params: &Constants, state: &mut [Fr]) -> Fr{
     let mut aux1 = vec![Fr::zero(); params.width];
     let mut aux2 = vec![Fr::zero(); params.width];
 
     // avoid auto params c index assert.
-    assert!(params.c.len() == params.round3);
+    assert!(params.c.len() == params.round3 || params.c.len() == params.round3 * params.width);
 
     (0..params.round3)
         .for_each(|i| {
-            state.iter_mut().for_each(|s| s.add_assign(&params.c[i]));
+            state.iter_mut()
+                .enumerate()
+                .for_each(|(j, s)| s.add_assign(round_constant(params, i, j)));
             sbox(params, state, &mut aux1, i);
-            mix(params, state, &mut aux1, &mut aux2);
+            mix_lazy(params, state, &mut aux2);
         });
 
     state[0]
 }
 
-#[pyfunction]
-pub fn poseidon_hash(inp: Vec<String>, t: usize) -> String {
-    let params_map = POSEIDON_PARAMS.read().unwrap();
-    let params = params_map.get(&t)
-        .expect("params t:{inp.len()} not initialized");
+/// Number of independent states permuted together by `hash_lanes`.
+pub const LANES: usize = 4;
 
-    let mut state = vec![Fr::zero(); params.width];
+/// A batch of `LANES` states in structure-of-arrays layout: `lanes[j][l]`
+/// is element `j` of the state in lane `l`, so every field op of a round
+/// is issued for all lanes back to back.
+pub type LaneState = Vec<[Fr; LANES]>;
 
-    inp.iter().zip(state.iter_mut())
-        .for_each(|(i, s)| *s = Fr::from_str(i).unwrap());
-    hash(params, &mut state).to_string()
+#[inline(always)]
+fn pow5_lanes(k: kernel::Kernel, s: &mut [Fr; LANES]) {
+    let aux = *s;
+    s.iter_mut().for_each(|s| k.square(s));
+    s.iter_mut().for_each(|s| k.square(s));
+    s.iter_mut().zip(aux.iter()).for_each(|(s, a)| k.mul(s, a));
 }
 
-#[pyfunction]
-pub fn multi_poseidon_hash(inp: Vec<String>, t: usize) -> Vec<String> {
-    let params_map = POSEIDON_PARAMS.read().unwrap();
-    let params = params_map.get(&t)
-        .expect("params t:{inp.len()} not initialized");
+/// Runs the permutation on `LANES` states at once. Every field op of a
+/// round is issued for all lanes back to back, so the independent
+/// multiplications of different lanes can overlap in the pipeline, which
+/// the `hash_lanes` bench puts at 10-20% over `LANES` calls to `hash`.
+/// Each lane ends up exactly as `hash` would leave it.
+///
+/// The code is scalar and an AVX2 path is out of scope: AVX2 only
+/// multiplies 32-bit halves, so vectorizing would need its own limb
+/// representation of `Fr` rather than the `ff` one used everywhere else.
+pub fn hash_lanes(params: &Constants, lanes: &mut [[Fr; LANES]]) {
+    assert!(params.c.len() == params.round3 || params.c.len() == params.round3 * params.width);
+    assert!(lanes.len() == params.width);
+
+    let k = kernel::kernel();
+    let mut res = vec![[Fr::zero(); LANES]; params.width];
+
+    for i in 0..params.round3 {
+        lanes.iter_mut()
+            .enumerate()
+            .for_each(|(j, s)| {
+                let c = round_constant(params, i, j);
+                s.iter_mut().for_each(|s| s.add_assign(c));
+            });
+
+        if i < params.round1 || i >= params.round2 {
+            lanes.iter_mut().for_each(|s| pow5_lanes(k, s));
+        } else {
+            pow5_lanes(k, &mut lanes[0]);
+        }
 
+        params.m
+            .iter()
+            .zip(res.iter_mut())
+            .for_each(|(ml, res)| {
+                let mut acc = [[0u64; 9]; LANES];
+                ml.iter()
+                    .zip(lanes.iter())
+                    .for_each(|(m, s)| {
+                        acc.iter_mut()
+                            .zip(s.iter())
+                            .for_each(|(acc, s)| mac_wide(acc, &(m.0).0, &(s.0).0));
+                    });
+                res.iter_mut()
+                    .zip(acc.iter())
+                    .for_each(|(r, acc)| *r = reduce_wide(*acc));
+            });
+
+        lanes.copy_from_slice(&res);
+    }
+}
+
+/// Fails unless `n_items` splits into chunks of `width - 1`.
+fn check_chunks(params: &Constants, n_items: usize) -> Result<(), Error> {
+    if !n_items.is_multiple_of(params.width - 1) {
+        return Err(Error::InvalidParams(format!(
+            "{} elements is not a multiple of t - 1 = {}", n_items, params.width - 1)));
+    }
+    Ok(())
+}
+
+/// Hashes consecutive chunks of `width - 1` inputs, `LANES` chunks per
+/// permutation, in parallel. Fails on a trailing partial chunk.
+pub fn hash_many(params: &Constants, inp: &[Fr]) -> Result<Vec<Fr>, Error> {
+    check_chunks(params, inp.len())?;
     let step = params.width - 1;
-    let ostep = params.width;
-    let mut output = vec![Fr::zero(); ostep * (inp.len() / step)];
+    let mut output = vec![Fr::zero(); inp.len() / step];
+
+    inp
+        .par_chunks(step * LANES)
+        .zip(output.par_chunks_mut(LANES))
+        .for_each(|(arr, out)| {
+            let mut lanes: LaneState = vec![[Fr::zero(); LANES]; params.width];
+            arr.chunks(step)
+                .enumerate()
+                .for_each(|(l, item)| {
+                    item.iter().zip(lanes.iter_mut())
+                        .for_each(|(i, s)| s[l] = *i);
+                });
+
+            hash_lanes(params, &mut lanes);
+            out.iter_mut()
+                .zip(lanes[0].iter())
+                .for_each(|(o, h)| *o = *h);
+        });
+
+    Ok(output)
+}
+
+/// Incremental sponge over a width `t` permutation. Elements are absorbed
+/// into the `t - 1` leading rate elements, the last one is the capacity,
+/// matching the layout `hash` is called with. The input is padded with a
+/// single one and zeros to a full block, so inputs of different lengths
+/// never collide.
+#[derive(Clone)]
+pub struct Sponge {
+    params: Arc<Constants>,
+    state: Vec<Fr>,
+    pending: Vec<Fr>,
+}
+
+impl Sponge {
+    pub fn new(params: Arc<Constants>) -> Sponge {
+        let state = vec![Fr::zero(); params.width];
+        Sponge {
+            pending: Vec::with_capacity(params.width - 1),
+            params,
+            state,
+        }
+    }
+
+    pub fn params(&self) -> &Arc<Constants> {
+        &self.params
+    }
+
+    /// Number of elements absorbed per permutation.
+    pub fn rate(&self) -> usize {
+        self.params.width - 1
+    }
+
+    fn absorb_block(&mut self) {
+        self.state.iter_mut()
+            .zip(self.pending.drain(..))
+            .for_each(|(s, p)| s.add_assign(&p));
+        hash(&self.params, &mut self.state);
+    }
+
+    pub fn absorb(&mut self, inp: &[Fr]) {
+        for i in inp {
+            self.pending.push(*i);
+            if self.pending.len() == self.rate() {
+                self.absorb_block();
+            }
+        }
+    }
 
+    /// Encodes the parameters, the state and the pending input, so a
+    /// partially fed sponge can be rebuilt elsewhere with `from_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let params = self.params.to_bytes();
+        let mut out = Vec::with_capacity(8 + params.len() + 32 * (self.state.len() + self.pending.len()));
+        out.extend_from_slice(&(params.len() as u32).to_le_bytes());
+        out.extend_from_slice(&params);
+        write_frs(&mut out, self.state.iter());
+        out.extend_from_slice(&(self.pending.len() as u32).to_le_bytes());
+        write_frs(&mut out, self.pending.iter());
+        out
+    }
+
+    pub fn from_bytes(b: &[u8]) -> Result<Sponge, Error> {
+        let mut r = Reader(b);
+        let n = r.u32()?;
+        let params = Constants::from_bytes(r.take(n)?)?;
+        let state = r.frs(params.width)?;
+        let n_pending = r.u32()?;
+        // a full block is absorbed as it completes, never left pending.
+        if n_pending >= params.width - 1 {
+            return Err(Error::InvalidParams(format!(
+                "{} pending elements for rate {}", n_pending, params.width - 1)));
+        }
+        let mut pending = r.frs(n_pending)?;
+        pending.reserve(params.width - 1 - n_pending);
+        r.finish()?;
+        Ok(Sponge { params: Arc::new(params), state, pending })
+    }
+
+    /// Pads and returns `n` output elements, leaving `self` untouched so
+    /// more input can still be absorbed. Fails with `InvalidParams` when
+    /// `n` elements cannot be allocated.
+    pub fn squeeze(&self, n: usize) -> Result<Vec<Fr>, Error> {
+        let mut output = Vec::new();
+        output.try_reserve_exact(n).map_err(|e| Error::InvalidParams(
+            format!("cannot squeeze {} elements: {}", n, e)))?;
+
+        let mut sponge = self.clone();
+        let rate = sponge.rate();
+        sponge.pending.push(Fr::one());
+        sponge.pending.resize(rate, Fr::zero());
+        sponge.absorb_block();
+
+        loop {
+            output.extend(sponge.state[..rate].iter().take(n - output.len()));
+            if output.len() == n {
+                return Ok(output);
+            }
+            hash(&sponge.params, &mut sponge.state);
+        }
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hex string.
+pub fn parse_bigint(s: &str) -> Result<BigInt, Error> {
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => BigInt::parse_bytes(hex.as_bytes(), 16),
+        None => BigInt::parse_bytes(s.as_bytes(), 10),
+    };
+    n.ok_or_else(|| Error::InvalidFieldElement(s.to_string()))
+}
+
+/// Parses a decimal or `0x`-prefixed hex string into a field element,
+/// rejecting values outside `[0, p)` instead of reducing them.
+pub fn parse_fr(s: &str) -> Result<Fr, Error> {
+    fr_from_bigint(&parse_bigint(s)?).map_err(|_| Error::InvalidFieldElement(s.to_string()))
+}
+
+/// Converts a non-negative integer below the modulus into a field element.
+pub fn fr_from_bigint(n: &BigInt) -> Result<Fr, Error> {
+    let (sign, bytes) = n.to_bytes_le();
+    if sign == Sign::Minus || bytes.len() > 32 {
+        return Err(Error::InvalidFieldElement(n.to_string()));
+    }
+    let mut le = [0u8; 32];
+    le[..bytes.len()].copy_from_slice(&bytes);
+    fr_from_bytes(&le, false).map_err(|_| Error::InvalidFieldElement(n.to_string()))
+}
+
+pub fn fr_to_biguint(f: &Fr) -> BigUint {
+    let mut le = [0u8; 32];
+    fr_to_bytes(f, false, &mut le);
+    BigUint::from_bytes_le(&le)
+}
+
+/// Reads a field element from four `u64` limbs, least significant first,
+/// in standard (non-Montgomery) form.
+pub fn fr_from_limbs(limbs: &[u64]) -> Result<Fr, Error> {
+    let mut repr = FrRepr::default();
+    if limbs.len() != repr.as_ref().len() {
+        return Err(Error::InvalidFieldElement(format!("{} limbs instead of 4", limbs.len())));
+    }
+    repr.as_mut().copy_from_slice(limbs);
+    Fr::from_repr(repr).map_err(|_| Error::InvalidFieldElement(repr.to_string()))
+}
+
+/// Reads a field element from a 32-byte row.
+pub fn fr_from_bytes(bytes: &[u8], big_endian: bool) -> Result<Fr, Error> {
+    let mut repr = FrRepr::default();
+    let read = if big_endian { repr.read_be(bytes) } else { repr.read_le(bytes) };
+    read.map_err(|e| Error::InvalidFieldElement(e.to_string()))?;
+    Fr::from_repr(repr).map_err(|_| Error::InvalidFieldElement(repr.to_string()))
+}
+
+/// Writes a field element as a 32-byte row.
+pub fn fr_to_bytes(f: &Fr, big_endian: bool, out: &mut [u8]) {
+    let repr = f.into_repr();
+    if big_endian {
+        repr.write_be(out).unwrap();
+    } else {
+        repr.write_le(out).unwrap();
+    }
+}
+
+/// Permutes `[init, inp...]`, circomlib's layout with the capacity element
+/// first, and returns the full state.
+fn permute_with_capacity(params: &Constants, inp: &[Fr], init: Fr) -> Vec<Fr> {
+    let mut state = vec![Fr::zero(); params.width];
+    state[0] = init;
+    state[1..=inp.len()].copy_from_slice(inp);
+    hash(params, &mut state);
+    state
+}
+
+/// Hashes each item with the parameters of width `item.len() + 1`, in
+/// parallel, returning the hashes in input order. Items are permuted in
+/// circomlib's layout, so each hash equals `poseidon(item, None)`. Fails on
+/// the first item whose width has no registered parameters rather than
+/// skipping it.
+pub fn hash_ragged(
+    params_map: &HashMap<usize, Constants>, inp: &[Vec<Fr>]) -> Result<Vec<Fr>, Error> {
     inp
-        .par_chunks(step)
-        .zip(output.par_chunks_mut(ostep))
-        .map(|(arr, state)| {
-            arr.iter().zip(state.iter_mut())
-                .for_each(|(i, s)| *s = Fr::from_str(i).unwrap());
+        .par_iter()
+        .map(|item| {
+            let t = item.len() + 1;
+            let params = params_map.get(&t)
+                .ok_or(Error::ParamsNotInitialized(t))?;
 
-            hash(params, state).to_string()
+            Ok(permute_with_capacity(params, item, Fr::zero())[0])
         })
         .collect()
 }
+
+/// circomlib-style hash: picks the registered parameters of width
+/// `inp.len() + 1`, so inputs are never padded or truncated, and permutes
+/// `[init_state, inp...]` with the capacity zero by default. Equal to
+/// circomlibjs `poseidon` once `circomlib_params` are registered.
+pub fn poseidon(inp: &[Fr], init_state: Option<Fr>) -> Result<Fr, Error> {
+    Ok(poseidon_ex(inp, init_state.unwrap_or_else(Fr::zero), 1)?[0])
+}
+
+/// Like circomlib's `PoseidonEx(nInputs, nOuts)`: the width is
+/// `inp.len() + 1`, the state starts as `[init_state, inp...]` and its
+/// first `n_outs` elements are returned.
+pub fn poseidon_ex(inp: &[Fr], init_state: Fr, n_outs: usize) -> Result<Vec<Fr>, Error> {
+    let t = inp.len() + 1;
+    if n_outs == 0 || n_outs > t {
+        return Err(Error::InvalidOutputCount(n_outs, t));
+    }
+    let params_map = POSEIDON_PARAMS.read().unwrap();
+    let params = params_map.get(&t)
+        .ok_or(Error::ParamsNotInitialized(t))?;
+
+    let mut state = permute_with_capacity(params, inp, init_state);
+    state.truncate(n_outs);
+    Ok(state)
+}
+
+/// Hashes `n_items` elements produced in parallel by `read`, `width - 1`
+/// per hash with the parameters registered for width `t`, failing on a
+/// trailing partial chunk.
+pub fn hash_items<F>(t: usize, n_items: usize, read: F) -> Result<Vec<Fr>, Error>
+where
+    F: Fn(usize) -> Result<Fr, Error> + Send + Sync,
+{
+    let params_map = POSEIDON_PARAMS.read().unwrap();
+    let params = params_map.get(&t)
+        .ok_or(Error::ParamsNotInitialized(t))?;
+
+    check_chunks(params, n_items)?;
+
+    let inp = (0..n_items)
+        .into_par_iter()
+        .map(read)
+        .collect::<Result<Vec<Fr>, Error>>()?;
+
+    hash_many(params, &inp)
+}
+
+/// Registers `circomlib_params` for every bundled width, once, as tests
+/// run in parallel over the global registry.
+#[cfg(test)]
+pub(crate) fn register_circomlib_params() {
+    static REGISTER: std::sync::Once = std::sync::Once::new();
+    REGISTER.call_once(|| {
+        for t in 2..10 {
+            register_params(circomlib_params(t).unwrap());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn random_constants(t: usize) -> Constants {
+        let mut rng = rand::thread_rng();
+        Constants {
+            c: (0..(8 + 57)).map(|_| rng.gen()).collect(),
+            m: (0..t).map(|_| (0..t).map(|_| rng.gen()).collect()).collect(),
+            width: t,
+            n_rounds_f: 8,
+            n_rounds_p: 57,
+            round1: 4,
+            round2: 4 + 57,
+            round3: 8 + 57,
+        }
+    }
+
+    #[test]
+    fn test_mix_lazy_matches_mix() {
+        let mut rng = rand::thread_rng();
+        for t in 2..=17 {
+            let params = random_constants(t);
+            for _ in 0..16 {
+                let state: Vec<Fr> = (0..t).map(|_| rng.gen()).collect();
+                let mut expected = state.clone();
+                let mut aux = vec![Fr::zero(); t];
+                let mut res = vec![Fr::zero(); t];
+                mix(&params, &mut expected, &mut aux, &mut res);
+
+                let mut got = state.clone();
+                mix_lazy(&params, &mut got, &mut res);
+                assert_eq!(expected, got, "t: {}", t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mix_lazy_max_entries() {
+        let mut p_minus_one = Fr::one();
+        p_minus_one.negate();
+        for t in [2, 5, 6, 17, 64] {
+            let mut params = random_constants(t);
+            params.m = vec![vec![p_minus_one; t]; t];
+            let state = vec![p_minus_one; t];
+
+            let mut expected = state.clone();
+            let mut aux = vec![Fr::zero(); t];
+            let mut res = vec![Fr::zero(); t];
+            mix(&params, &mut expected, &mut aux, &mut res);
+
+            let mut got = state;
+            mix_lazy(&params, &mut got, &mut res);
+            assert_eq!(expected, got, "t: {}", t);
+        }
+    }
+
+    #[test]
+    fn test_hash_matches_reference_mix() {
+        let mut rng = rand::thread_rng();
+        for t in 2..=9 {
+            let params = random_constants(t);
+            let state: Vec<Fr> = (0..t).map(|_| rng.gen()).collect();
+
+            let mut expected = state.clone();
+            let mut aux1 = vec![Fr::zero(); t];
+            let mut aux2 = vec![Fr::zero(); t];
+            for i in 0..params.round3 {
+                expected.iter_mut().for_each(|s| s.add_assign(&params.c[i]));
+                sbox(&params, &mut expected, &mut aux1, i);
+                mix(&params, &mut expected, &mut aux1, &mut aux2);
+            }
+
+            let mut got = state;
+            assert_eq!(expected[0], hash(&params, &mut got));
+            assert_eq!(expected, got);
+        }
+    }
+
+    #[test]
+    fn test_hash_many_matches_hash() {
+        let mut rng = rand::thread_rng();
+        for t in 2..=9 {
+            let params = random_constants(t);
+            // two full lane groups plus a partial group.
+            let n = 2 * LANES + 1;
+            let inp: Vec<Fr> = (0..n * (t - 1)).map(|_| rng.gen()).collect();
+
+            let expected: Vec<Fr> = inp
+                .chunks_exact(t - 1)
+                .map(|arr| {
+                    let mut state = vec![Fr::zero(); t];
+                    state[..t - 1].copy_from_slice(arr);
+                    hash(&params, &mut state)
+                })
+                .collect();
+
+            assert_eq!(Ok(expected), hash_many(&params, &inp), "t: {}", t);
+            if t > 2 {
+                assert!(hash_many(&params, &inp[1..]).is_err(), "t: {}", t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_ragged() {
+        let mut rng = rand::thread_rng();
+        let mut params_map = HashMap::new();
+        for t in 2..=4 {
+            params_map.insert(t, random_constants(t));
+        }
+
+        let inp: Vec<Vec<Fr>> = [3, 1, 2, 2, 1]
+            .iter()
+            .map(|n| (0..*n).map(|_| rng.gen()).collect())
+            .collect();
+        let expected: Vec<Fr> = inp
+            .iter()
+            .map(|item| {
+                let mut state = vec![Fr::zero()];
+                state.extend_from_slice(item);
+                hash(&params_map[&state.len()], &mut state)
+            })
+            .collect();
+        assert_eq!(Ok(expected), hash_ragged(&params_map, &inp));
+
+        register_circomlib_params();
+        let expected = inp
+            .iter()
+            .map(|item| poseidon(item, None))
+            .collect::<Result<Vec<Fr>, Error>>();
+        assert_eq!(expected, hash_ragged(&POSEIDON_PARAMS.read().unwrap(), &inp));
+
+        let mut inp = inp;
+        inp.push(vec![Fr::one(); 4]);
+        assert_eq!(Err(Error::ParamsNotInitialized(5)), hash_ragged(&params_map, &inp));
+        assert_eq!(Err(Error::ParamsNotInitialized(1)), hash_ragged(&params_map, &[vec![]]));
+    }
+
+    #[test]
+    fn test_sponge_incremental() {
+        let mut rng = rand::thread_rng();
+        let params = Arc::new(random_constants(3));
+        let inp: Vec<Fr> = (0..7).map(|_| rng.gen()).collect();
+
+        let mut one_shot = Sponge::new(params.clone());
+        one_shot.absorb(&inp);
+        let mut chunked = Sponge::new(params.clone());
+        inp.chunks(3).for_each(|c| chunked.absorb(c));
+        assert_eq!(one_shot.squeeze(5).unwrap(), chunked.squeeze(5).unwrap());
+        assert_eq!(one_shot.squeeze(5).unwrap()[..2], one_shot.squeeze(2).unwrap()[..]);
+
+        // padding separates a trailing zero from a shorter input.
+        let mut padded = Sponge::new(params);
+        padded.absorb(&inp);
+        padded.absorb(&[Fr::zero()]);
+        assert_ne!(one_shot.squeeze(1).unwrap(), padded.squeeze(1).unwrap());
+    }
+
+    #[test]
+    fn test_sponge_bytes_roundtrip() {
+        let mut rng = rand::thread_rng();
+        let mut sponge = Sponge::new(Arc::new(random_constants(4)));
+        sponge.absorb(&(0..5).map(|_| rng.gen()).collect::<Vec<Fr>>());
+
+        let bytes = sponge.to_bytes();
+        let mut restored = Sponge::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.params().c, sponge.params().c);
+        assert_eq!(restored.params().m, sponge.params().m);
+        assert_eq!(restored.squeeze(3).unwrap(), sponge.squeeze(3).unwrap());
+
+        let x: Fr = rng.gen();
+        sponge.absorb(&[x]);
+        restored.absorb(&[x]);
+        assert_eq!(restored.squeeze(1).unwrap(), sponge.squeeze(1).unwrap());
+
+        assert!(Sponge::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Constants::from_bytes(&[0u8; 12]).is_err());
+        assert!(sponge.squeeze(usize::MAX).is_err());
+
+        // rate 3 allows at most two pending elements.
+        let mut full = sponge.to_bytes();
+        let at = full.len() - 32 * sponge.pending.len() - 4;
+        full.truncate(at);
+        full.extend_from_slice(&3u32.to_le_bytes());
+        write_frs(&mut full, [Fr::one(); 3].iter());
+        assert!(matches!(Sponge::from_bytes(&full), Err(Error::InvalidParams(_))));
+        full.truncate(at);
+        full.extend_from_slice(&2u32.to_le_bytes());
+        write_frs(&mut full, [Fr::one(); 2].iter());
+        assert!(Sponge::from_bytes(&full).is_ok());
+    }
+
+    #[test]
+    fn test_fr_from_limbs() {
+        assert_eq!(fr_from_limbs(&[1, 0, 0, 0]), Ok(Fr::one()));
+        assert!(fr_from_limbs(&[1, 0, 0]).is_err());
+        assert!(fr_from_limbs(&[1, 0, 0, 0, 0]).is_err());
+        assert!(fr_from_limbs(&[]).is_err());
+        assert!(fr_from_limbs(&[u64::MAX; 4]).is_err());
+    }
+
+    #[test]
+    fn test_parse_bigint_and_fr() {
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let p = BigInt::from(fr_to_biguint(&minus_one)) + 1u8;
+        assert_eq!(parse_bigint("255"), Ok(BigInt::from(255)));
+        assert_eq!(parse_bigint("0xff"), Ok(BigInt::from(255)));
+        assert_eq!(parse_bigint("0xFF"), Ok(BigInt::from(255)));
+        assert_eq!(parse_bigint("-7"), Ok(BigInt::from(-7)));
+        assert_eq!(parse_bigint(&p.to_string()), Ok(p.clone()));
+        for bad in &["", "0x", "12a", "0xzz", "ff", " 1", "1.5"] {
+            assert!(parse_bigint(bad).is_err(), "{:?}", bad);
+            assert!(parse_fr(bad).is_err(), "{:?}", bad);
+        }
+
+        assert_eq!(parse_fr("0"), Ok(Fr::zero()));
+        assert_eq!(parse_fr("0x1"), Ok(Fr::one()));
+        assert_eq!(parse_fr("255"), parse_fr("0xff"));
+        let max = (&p - 1u8).to_string();
+        assert_eq!(parse_fr(&max).map(|f| fr_to_biguint(&f).to_string()), Ok(max));
+        assert!(parse_fr("-1").is_err());
+        assert!(parse_fr(&p.to_string()).is_err());
+        assert!(parse_fr(&format!("0x{}", p.to_str_radix(16))).is_err());
+        assert!(parse_fr(&(&p + 1u8).to_string()).is_err());
+        assert!(parse_fr(&(BigInt::from(1u8) << 256usize).to_string()).is_err());
+    }
+
+    fn frs(v: &[&str]) -> Vec<Fr> {
+        v.iter().map(|s| parse_fr(s).unwrap()).collect()
+    }
+
+    // expected values from circomlibjs `poseidon`, whose reference
+    // implementation keeps the capacity first and adds one constant per
+    // element.
+    #[test]
+    fn test_circomlib_vectors() {
+        register_circomlib_params();
+        assert_eq!(poseidon(&frs(&["1"]), None), parse_fr(
+            "18586133768512220936620570745912940619677854269274689475585506675881198879027"));
+        assert_eq!(poseidon(&frs(&["1", "2"]), None), parse_fr(
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530"));
+        assert_eq!(poseidon(&frs(&["1", "2", "3", "4", "5", "6"]), None), parse_fr(
+            "20400040500897583745843009878988256314335038853985262692600694741116813247201"));
+        assert_eq!(poseidon(&frs(&["1", "2", "3", "4", "5", "6", "7", "8"]), None), parse_fr(
+            "18604317144381847857886385684060986177838410221561136253933256952257712543953"));
+
+        // poseidon([1, 2], 0, 3)
+        assert_eq!(poseidon_ex(&frs(&["1", "2"]), Fr::zero(), 3), Ok(frs(&[
+            "0x115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a",
+            "0x0fca49b798923ab0239de1c9e7a4a9a2210312b6a2f616d18b5a87f9b628ae29",
+            "0x0e7ae82e40091e63cbd4f16a6d16310b3729d4b6e138fcf54110e2867045a30c",
+        ])));
+        // poseidon([1, 2, 3, 4], 0, 5)
+        assert_eq!(poseidon_ex(&frs(&["1", "2", "3", "4"]), Fr::zero(), 5), Ok(frs(&[
+            "0x299c867db6c1fdd79dcefa40e4510b9837e60ebb1ce0663dbaa525df65250465",
+            "0x1148aaef609aa338b27dafd89bb98862d8bb2b429aceac47d86206154ffe053d",
+            "0x24febb87fed7462e23f6665ff9a0111f4044c38ee1672c1ac6b0637d34f24907",
+            "0x0eb08f6d809668a981c186beaf6110060707059576406b248e5d9cf6e78b3d3e",
+            "0x07748bc6877c9b82c8b98666ee9d0626ec7f5be4205f79ee8528ef1c4a376fc7",
+        ])));
+
+        // poseidon([1, 2], 7, 3) and poseidon([1, 2, 3, 4], 5, 2)
+        assert_eq!(poseidon_ex(&frs(&["1", "2"]), frs(&["7"])[0], 3), Ok(frs(&[
+            "7540134072963094781551849189553249853827772934652778250146343743716337940736",
+            "9416154640693989499515053031030659859852016023578551600909268811390724747953",
+            "3980480193631752437841939048985062961895463609238460158469593834428459977044",
+        ])));
+        assert_eq!(poseidon_ex(&frs(&["1", "2", "3", "4"]), frs(&["5"])[0], 2), Ok(frs(&[
+            "10186559106334413524732706754528145656067391673456012863272182399655785764304",
+            "2092803138655502179585792955519507187034693022495921182710250285184988869562",
+        ])));
+        // poseidon([1], 3)
+        assert_eq!(poseidon(&frs(&["1"]), Some(frs(&["3"])[0])), parse_fr(
+            "9896565238657596317307767813541775936202751971066221799238874954464990669413"));
+    }
+
+    #[test]
+    fn test_circomlib_params() {
+        assert!(circomlib_params(1).is_err());
+        assert!(circomlib_params(10).is_err());
+        let params = circomlib_params(3).unwrap();
+        assert_eq!((params.n_rounds_f, params.n_rounds_p, params.c.len()), (8, 57, 3 * 65));
+
+        let restored = Constants::from_bytes(&params.to_bytes()).unwrap();
+        assert_eq!(restored.c, params.c);
+
+        // round counts whose sums or products overflow are rejected.
+        for (f, p) in &[(usize::MAX, 1), (usize::MAX / 2, usize::MAX / 2)] {
+            let res = Constants::new(3, *f, *p, params.c.clone(), params.m.clone());
+            assert!(matches!(res, Err(Error::InvalidParams(_))), "{} {}", f, p);
+        }
+        assert_eq!(restored.m, params.m);
+
+        // per-element constants go through the lanes like through `hash`.
+        let mut rng = rand::thread_rng();
+        let inp: Vec<Fr> = (0..2 * LANES).map(|_| rng.gen()).collect();
+        let expected: Vec<Fr> = inp.chunks(2)
+            .map(|item| hash(&params, &mut [item[0], item[1], Fr::zero()]))
+            .collect();
+        assert_eq!(hash_many(&params, &inp), Ok(expected));
+    }
+
+    #[test]
+    fn test_poseidon_width_and_init() {
+        // widths no other test registers.
+        let (t, unregistered) = (12, 13);
+        let params = random_constants(t);
+        register_params(params.clone());
+
+        let mut rng = rand::thread_rng();
+        let inp: Vec<Fr> = (0..t - 1).map(|_| rng.gen()).collect();
+        let init: Fr = rng.gen();
+
+        let mut state = vec![Fr::zero()];
+        state.extend_from_slice(&inp);
+        assert_eq!(poseidon(&inp, None), Ok(hash(&params, &mut state.clone())));
+        assert_eq!(poseidon(&inp, Some(Fr::zero())), poseidon(&inp, None));
+
+        state[0] = init;
+        let h = hash(&params, &mut state);
+        assert_eq!(poseidon(&inp, Some(init)), Ok(h));
+        assert_eq!(poseidon_ex(&inp, init, t), Ok(state));
+        assert_ne!(poseidon(&inp, Some(init)), poseidon(&inp, None));
+
+        let mut longer = inp.clone();
+        longer.push(Fr::one());
+        assert_eq!(poseidon(&longer, None), Err(Error::ParamsNotInitialized(unregistered)));
+        assert_eq!(poseidon_ex(&longer, init, 2), Err(Error::ParamsNotInitialized(unregistered)));
+        assert_eq!(poseidon_ex(&inp, init, 0), Err(Error::InvalidOutputCount(0, t)));
+        assert_eq!(poseidon_ex(&inp, init, t + 1), Err(Error::InvalidOutputCount(t + 1, t)));
+    }
+}
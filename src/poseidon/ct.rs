@@ -0,0 +1,183 @@
+// Constant-time `Fr` arithmetic for secret-dependent computations.
+//
+// The `ff` derive and the kernels end their operations with a conditional
+// subtraction of the modulus, which branches on the value. These versions
+// always compute the subtraction and pick the result with a mask, so their
+// running time does not depend on their operands.
+
+use super::{adc, mac, sbb, Fr, FrRepr, INV, MODULUS};
+
+/// `b` when `choice` is 1, `a` when it is 0.
+#[inline(always)]
+pub fn select(a: &Fr, b: &Fr, choice: u64) -> Fr {
+    Fr(FrRepr(select_limbs(&(a.0).0, &(b.0).0, choice)))
+}
+
+/// 1 when `a == b`, 0 otherwise.
+#[inline(always)]
+pub fn eq_u64(a: u64, b: u64) -> u64 {
+    let x = a ^ b;
+    // `x | -x` has the top bit set exactly when x is non-zero.
+    1 ^ ((x | x.wrapping_neg()) >> 63)
+}
+
+pub fn add(a: &Fr, b: &Fr) -> Fr {
+    Fr(FrRepr(add_mod(&(a.0).0, &(b.0).0, &MODULUS.0)))
+}
+
+pub fn sub(a: &Fr, b: &Fr) -> Fr {
+    Fr(FrRepr(sub_mod(&(a.0).0, &(b.0).0, &MODULUS.0)))
+}
+
+pub fn neg(a: &Fr) -> Fr {
+    sub(&Fr(FrRepr([0; 4])), a)
+}
+
+pub fn mul(a: &Fr, b: &Fr) -> Fr {
+    Fr(FrRepr(mont_mul(&(a.0).0, &(b.0).0, &MODULUS.0, INV)))
+}
+
+// The same operations on bare limbs, for any modulus `m` below `2^255`
+// with `inv = -m^-1 mod 2^64`, so other prime fields such as
+// `babyjubjub::Scalar` can share them.
+
+/// `b` when `choice` is 1, `a` when it is 0.
+#[inline(always)]
+pub(crate) fn select_limbs(a: &[u64; 4], b: &[u64; 4], choice: u64) -> [u64; 4] {
+    // hidden from the optimizer, which would otherwise turn the mask back
+    // into a branch.
+    let mask = std::hint::black_box(choice).wrapping_neg();
+    let mut r = [0u64; 4];
+    for ((r, a), b) in r.iter_mut().zip(a.iter()).zip(b.iter()) {
+        *r = a ^ (mask & (a ^ b));
+    }
+    r
+}
+
+/// Reduces `t`, below `2m`, with `hi` its bit above the four limbs.
+#[inline(always)]
+fn reduce_once(t: [u64; 4], hi: u64, m: &[u64; 4]) -> [u64; 4] {
+    let mut borrow = 0;
+    let mut s = [0u64; 4];
+    for i in 0..4 {
+        s[i] = sbb(t[i], m[i], &mut borrow);
+    }
+    // keep t when t < m, that is when subtracting borrowed past `hi`.
+    let (_, keep) = hi.overflowing_sub(borrow);
+    select_limbs(&s, &t, keep as u64)
+}
+
+/// `a + b mod m`, for `a` and `b` below `m`.
+pub(crate) fn add_mod(a: &[u64; 4], b: &[u64; 4], m: &[u64; 4]) -> [u64; 4] {
+    let mut carry = 0;
+    let mut t = [0u64; 4];
+    for (i, t) in t.iter_mut().enumerate() {
+        *t = adc(a[i], b[i], &mut carry);
+    }
+    reduce_once(t, carry, m)
+}
+
+/// `a - b mod m`, for `a` and `b` below `m`.
+pub(crate) fn sub_mod(a: &[u64; 4], b: &[u64; 4], m: &[u64; 4]) -> [u64; 4] {
+    let mut borrow = 0;
+    let mut t = [0u64; 4];
+    for (i, t) in t.iter_mut().enumerate() {
+        *t = sbb(a[i], b[i], &mut borrow);
+    }
+    // add m back when the subtraction borrowed.
+    let mask = std::hint::black_box(borrow).wrapping_neg();
+    let mut carry = 0;
+    for (t, m) in t.iter_mut().zip(m.iter()) {
+        *t = adc(*t, m & mask, &mut carry);
+    }
+    t
+}
+
+/// `a * b / 2^256 mod m` by CIOS, as the kernels', with the final
+/// subtraction masked. `a * b` must be below `m * 2^256`, as it is when
+/// both are below `m` or when one is below `m` and the other any 256-bit
+/// value.
+pub(crate) fn mont_mul(a: &[u64; 4], b: &[u64; 4], m: &[u64; 4], inv: u64) -> [u64; 4] {
+    let mut t = [0u64; 6];
+
+    for bi in b.iter() {
+        let mut carry = 0;
+        for j in 0..4 {
+            t[j] = mac(t[j], a[j], *bi, &mut carry);
+        }
+        t[4] = adc(t[4], 0, &mut carry);
+        t[5] = carry;
+
+        let k = t[0].wrapping_mul(inv);
+        let mut carry = 0;
+        mac(t[0], k, m[0], &mut carry);
+        for j in 1..4 {
+            t[j - 1] = mac(t[j], k, m[j], &mut carry);
+        }
+        t[3] = adc(t[4], 0, &mut carry);
+        t[4] = t[5] + carry;
+    }
+
+    reduce_once([t[0], t[1], t[2], t[3]], t[4], m)
+}
+
+/// `a^(p - 2)`, the inverse of a non-zero `a`, by square-and-multiply
+/// over the public exponent; zero maps to zero.
+pub fn inverse(a: &Fr) -> Fr {
+    let mut e = MODULUS.0;
+    e[0] -= 2;
+    let mut r = Fr(FrRepr(R));
+    for i in (0..256).rev() {
+        r = mul(&r, &r);
+        if (e[i / 64] >> (i % 64)) & 1 == 1 {
+            r = mul(&r, a);
+        }
+    }
+    r
+}
+
+/// One in Montgomery form, `2^256 mod p`.
+const R: [u64; 4] = [
+    0xac96341c4ffffffb,
+    0x36fc76959f60cd29,
+    0x666ea36f7879462e,
+    0x0e0a77c19a07df2f,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use rand::Rng;
+
+    #[test]
+    fn test_ct_matches_field() {
+        let mut rng = rand::thread_rng();
+        let mut p_minus_one = Fr::one();
+        p_minus_one.negate();
+        let mut samples: Vec<Fr> = (0..500).map(|_| rng.gen()).collect();
+        samples.extend_from_slice(&[Fr::zero(), Fr::one(), p_minus_one]);
+        assert_eq!(Fr(FrRepr(R)), Fr::one());
+
+        for (a, b) in samples.iter().zip(samples.iter().rev()) {
+            let mut e = *a;
+            e.add_assign(b);
+            assert_eq!(add(a, b), e);
+            let mut e = *a;
+            e.sub_assign(b);
+            assert_eq!(sub(a, b), e);
+            let mut e = *a;
+            e.negate();
+            assert_eq!(neg(a), e);
+            let mut e = *a;
+            e.mul_assign(b);
+            assert_eq!(mul(a, b), e);
+            assert_eq!(select(a, b, 0), *a);
+            assert_eq!(select(a, b, 1), *b);
+        }
+        for a in samples.iter().take(20) {
+            assert_eq!(Some(inverse(a)), a.inverse().or(Some(Fr::zero())));
+        }
+        assert_eq!((eq_u64(3, 3), eq_u64(3, 4), eq_u64(0, u64::MAX)), (1, 0, 0));
+    }
+}
@@ -0,0 +1,150 @@
+// Runtime-selected kernels for `Fr` multiplication and squaring.
+//
+// The only kernel is the portable one, the `ff` derive's own arithmetic.
+// A CIOS multiplication compiled for `bmi2`/`adx` did not beat it in the
+// `kernel` benches of `bench_poseidon_hash`, and ark-ff's assembly needs a
+// nightly compiler with the target features enabled at build time, so it
+// cannot be picked at runtime. A faster kernel goes in as a `Kernel`
+// variant that `is_supported` gates on the CPU, with `detect` choosing it.
+
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use ff::Field;
+
+use super::Fr;
+
+/// Environment variable read on first use to force a kernel by name.
+pub const KERNEL_ENV: &str = "CRYPTO_RS_FR_KERNEL";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    Portable,
+}
+
+impl Kernel {
+    /// All kernels, the default first.
+    pub const ALL: [Kernel; 1] = [Kernel::Portable];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kernel::Portable => "portable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Kernel> {
+        Kernel::ALL.iter().copied().find(|k| k.name() == name)
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            Kernel::Portable => true,
+        }
+    }
+
+    #[inline(always)]
+    pub fn mul(self, a: &mut Fr, b: &Fr) {
+        match self {
+            Kernel::Portable => a.mul_assign(b),
+        }
+    }
+
+    #[inline(always)]
+    pub fn square(self, a: &mut Fr) {
+        match self {
+            Kernel::Portable => a.square(),
+        }
+    }
+
+    fn index(self) -> u8 {
+        match self {
+            Kernel::Portable => 1,
+        }
+    }
+}
+
+// 0 means not detected yet, otherwise `Kernel::index`.
+static SELECTED: AtomicU8 = AtomicU8::new(0);
+
+/// The kernel named by `KERNEL_ENV` when this CPU supports it, the
+/// portable one otherwise.
+fn detect() -> Kernel {
+    env::var(KERNEL_ENV)
+        .ok()
+        .and_then(|name| Kernel::from_name(&name))
+        .filter(|k| k.is_supported())
+        .unwrap_or(Kernel::Portable)
+}
+
+/// The kernel used by `mul` and `square`.
+#[inline(always)]
+pub fn kernel() -> Kernel {
+    match SELECTED.load(Ordering::Relaxed) {
+        1 => Kernel::Portable,
+        _ => {
+            let k = detect();
+            SELECTED.store(k.index(), Ordering::Relaxed);
+            k
+        }
+    }
+}
+
+/// Forces `k` for all later field operations, returning false and leaving
+/// the selection untouched when this CPU does not support it.
+pub fn set_kernel(k: Kernel) -> bool {
+    if !k.is_supported() {
+        return false;
+    }
+    SELECTED.store(k.index(), Ordering::Relaxed);
+    true
+}
+
+#[inline(always)]
+pub fn mul(a: &mut Fr, b: &Fr) {
+    kernel().mul(a, b)
+}
+
+#[inline(always)]
+pub fn square(a: &mut Fr) {
+    kernel().square(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_kernels_match_portable() {
+        let mut rng = rand::thread_rng();
+        let mut p_minus_one = Fr::one();
+        p_minus_one.negate();
+        let mut samples: Vec<Fr> = (0..1000).map(|_| rng.gen()).collect();
+        samples.extend_from_slice(&[Fr::zero(), Fr::one(), p_minus_one]);
+
+        for k in Kernel::ALL.iter().filter(|k| k.is_supported()) {
+            for (a, b) in samples.iter().zip(samples.iter().rev()) {
+                let mut expected = *a;
+                Kernel::Portable.mul(&mut expected, b);
+                let mut got = *a;
+                k.mul(&mut got, b);
+                assert_eq!(expected, got, "{} mul", k.name());
+
+                let mut expected = *a;
+                Kernel::Portable.square(&mut expected);
+                let mut got = *a;
+                k.square(&mut got);
+                assert_eq!(expected, got, "{} square", k.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernel_names() {
+        for k in Kernel::ALL.iter() {
+            assert_eq!(Some(*k), Kernel::from_name(k.name()));
+        }
+        assert!(Kernel::Portable.is_supported());
+        assert!(Kernel::from_name("avx512").is_none());
+    }
+}
@@ -0,0 +1,1142 @@
+// Python bindings: the types and pyfunctions of the `crypto_rs` module, over
+// the pure-Rust API of the other modules.
+
+// pyo3's #[pymethods] expansion defines impls inside a const item, and
+// create_exception! checks a cfg only pyo3's own build script sets.
+#![allow(non_local_definitions, unexpected_cfgs)]
+
+use std::sync::Arc;
+
+use ff::*;
+use num_bigint::{BigInt, BigUint, Sign};
+use pyo3::basic::CompareOp;
+use pyo3::buffer::PyBuffer;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyTypeError, PyValueError, PyZeroDivisionError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyLong, PyType};
+use rayon::prelude::*;
+
+use crate::babyjubjub::{self, Point, PrivateKey, Signature, BASE8};
+use crate::error::{Error, Rejection};
+use crate::poseidon::{self, Constants, Fr, Sponge, POSEIDON_PARAMS};
+
+create_exception!(
+    crypto_rs, CryptoRsError, PyValueError,
+    "Base class of the errors raised by crypto_rs."
+);
+create_exception!(
+    crypto_rs, InvalidFieldElement, CryptoRsError,
+    "A value that is not an element of the field."
+);
+create_exception!(
+    crypto_rs, ParamsNotInitialized, CryptoRsError,
+    "No Poseidon parameters registered for the width."
+);
+create_exception!(
+    crypto_rs, InvalidParams, CryptoRsError,
+    "Unusable Poseidon parameters or call parameters."
+);
+create_exception!(
+    crypto_rs, InvalidPoint, CryptoRsError,
+    "Coordinates that are not a point of BabyJubJub."
+);
+create_exception!(
+    crypto_rs, InvalidSignature, CryptoRsError,
+    "A malformed EdDSA signature."
+);
+create_exception!(
+    crypto_rs, SignatureRejected, InvalidSignature,
+    "A signature that does not verify; args[1] names the reason."
+);
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> PyErr {
+        let msg = e.to_string();
+        match e {
+            Error::ParamsNotInitialized(_) => ParamsNotInitialized::new_err(msg),
+            Error::InvalidFieldElement(_) => InvalidFieldElement::new_err(msg),
+            Error::InvalidOutputCount(..) | Error::InvalidParams(_) => InvalidParams::new_err(msg),
+            Error::InvalidPoint(_) => InvalidPoint::new_err(msg),
+            Error::InvalidSignature(_) => InvalidSignature::new_err(msg),
+            Error::SignatureRejected(r) => SignatureRejected::new_err((msg, r.name())),
+        }
+    }
+}
+
+/// Adds the exception hierarchy to the extension module.
+pub fn add_exceptions(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("CryptoRsError", py.get_type::<CryptoRsError>())?;
+    m.add("InvalidFieldElement", py.get_type::<InvalidFieldElement>())?;
+    m.add("ParamsNotInitialized", py.get_type::<ParamsNotInitialized>())?;
+    m.add("InvalidParams", py.get_type::<InvalidParams>())?;
+    m.add("InvalidPoint", py.get_type::<InvalidPoint>())?;
+    m.add("InvalidSignature", py.get_type::<InvalidSignature>())?;
+    m.add("SignatureRejected", py.get_type::<SignatureRejected>())?;
+    Ok(())
+}
+
+/// Anything the Python API accepts as a field element: an `Fr`, an `int`,
+/// `bytes` holding an integer of at most 32 bytes, or a decimal or `0x`
+/// hex `str`. Values must lie in `[0, p)`, nothing is reduced silently.
+/// Bytes are read in the `byteorder` argument of the call, big-endian by
+/// default and always in the `Fr` operators, which take no arguments.
+#[derive(Clone)]
+pub enum FieldInput {
+    Fr(PyFr),
+    Int(BigInt),
+    Bytes(Vec<u8>),
+    Str(String),
+}
+
+impl<'source> FromPyObject<'source> for FieldInput {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        if let Ok(f) = ob.extract::<PyFr>() {
+            Ok(FieldInput::Fr(f))
+        } else if ob.is_instance_of::<PyLong>()? {
+            Ok(FieldInput::Int(ob.extract()?))
+        } else if let Ok(b) = ob.downcast::<PyBytes>() {
+            Ok(FieldInput::Bytes(b.as_bytes().to_vec()))
+        } else if let Ok(s) = ob.extract::<String>() {
+            Ok(FieldInput::Str(s))
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "expected Fr, int, bytes or str, got {}", ob.get_type().name()?)))
+        }
+    }
+}
+
+impl FieldInput {
+    /// Reads bytes big-endian, the default `byteorder`.
+    pub fn to_fr(&self) -> Result<Fr, Error> {
+        self.to_fr_ordered(true)
+    }
+
+    pub fn to_fr_ordered(&self, big_endian: bool) -> Result<Fr, Error> {
+        match self {
+            FieldInput::Fr(f) => Ok(f.0),
+            FieldInput::Str(s) => poseidon::parse_fr(s),
+            _ => poseidon::fr_from_bigint(&self.to_bigint(big_endian)?),
+        }
+    }
+
+    /// The integer value, not reduced, for inputs that are scalars rather
+    /// than field elements.
+    pub fn to_bigint(&self, big_endian: bool) -> Result<BigInt, Error> {
+        match self {
+            FieldInput::Fr(f) => Ok(poseidon::fr_to_biguint(&f.0).into()),
+            FieldInput::Int(n) => Ok(n.clone()),
+            FieldInput::Bytes(b) if b.len() > 32 => {
+                Err(Error::InvalidFieldElement(format!("{} bytes", b.len())))
+            }
+            FieldInput::Bytes(b) if big_endian => Ok(BigInt::from_bytes_be(Sign::Plus, b)),
+            FieldInput::Bytes(b) => Ok(BigInt::from_bytes_le(Sign::Plus, b)),
+            FieldInput::Str(s) => poseidon::parse_bigint(s),
+        }
+    }
+}
+
+pub fn to_frs(inp: &[FieldInput], big_endian: bool) -> Result<Vec<Fr>, Error> {
+    inp.iter().map(|i| i.to_fr_ordered(big_endian)).collect()
+}
+
+/// How results are handed back to Python.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// The `Fr(0x...)` strings the API has always returned.
+    Legacy,
+    Int,
+    Decimal,
+    Hex,
+    Bytes,
+    Fr,
+}
+
+/// The `byteorder` and `output` keyword arguments of a call.
+#[derive(Clone, Copy, Debug)]
+pub struct Encoding {
+    pub output: Output,
+    pub big_endian: bool,
+}
+
+/// A result converted off the GIL, ready to become a Python object.
+pub enum Encoded {
+    Str(String),
+    Int(BigUint),
+    Bytes([u8; 32]),
+    Fr(Fr),
+}
+
+impl Encoding {
+    pub fn new(output: &str, byteorder: &str) -> PyResult<Encoding> {
+        let output = match output {
+            "legacy" => Output::Legacy,
+            "int" => Output::Int,
+            "decimal" => Output::Decimal,
+            "hex" => Output::Hex,
+            "bytes" => Output::Bytes,
+            "fr" => Output::Fr,
+            _ => return Err(PyValueError::new_err(
+                "output must be one of 'legacy', 'int', 'decimal', 'hex', 'bytes' or 'fr'")),
+        };
+        Ok(Encoding { output, big_endian: big_endian(byteorder)? })
+    }
+
+    pub fn encode(&self, f: &Fr) -> Encoded {
+        match self.output {
+            Output::Legacy => Encoded::Str(f.to_string()),
+            Output::Int => Encoded::Int(poseidon::fr_to_biguint(f)),
+            Output::Decimal => Encoded::Str(poseidon::fr_to_biguint(f).to_string()),
+            Output::Hex => Encoded::Str(f.into_repr().to_string()),
+            Output::Bytes => {
+                let mut out = [0u8; 32];
+                poseidon::fr_to_bytes(f, self.big_endian, &mut out);
+                Encoded::Bytes(out)
+            }
+            Output::Fr => Encoded::Fr(*f),
+        }
+    }
+}
+
+impl IntoPy<PyObject> for Encoded {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            Encoded::Str(s) => s.into_py(py),
+            Encoded::Int(n) => n.into_py(py),
+            Encoded::Bytes(b) => PyBytes::new(py, &b).into(),
+            Encoded::Fr(f) => PyFr(f).into_py(py),
+        }
+    }
+}
+
+/// Maps a `byteorder` argument, as in `int.to_bytes`, to big-endian or not.
+pub fn big_endian(byteorder: &str) -> PyResult<bool> {
+    match byteorder {
+        "big" => Ok(true),
+        "little" => Ok(false),
+        _ => Err(PyValueError::new_err("byteorder must be either 'little' or 'big'")),
+    }
+}
+
+/// An element of the BN254 scalar field, the field `poseidon` hashes over.
+#[pyclass(name = "Fr", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PyFr(pub Fr);
+
+#[pymethods]
+impl PyFr {
+    #[new]
+    #[args(value = "None", byteorder = "\"big\"")]
+    fn new(value: Option<FieldInput>, byteorder: &str) -> PyResult<Self> {
+        let big_endian = big_endian(byteorder)?;
+        match value {
+            Some(v) => Ok(PyFr(v.to_fr_ordered(big_endian)?)),
+            None => Ok(PyFr(Fr::zero())),
+        }
+    }
+
+    #[staticmethod]
+    #[args(byteorder = "\"big\"")]
+    fn from_bytes(b: &[u8], byteorder: &str) -> PyResult<Self> {
+        if b.len() != 32 {
+            return Err(PyValueError::new_err("expected 32 bytes"));
+        }
+        Ok(PyFr(poseidon::fr_from_bytes(b, big_endian(byteorder)?)?))
+    }
+
+    #[staticmethod]
+    fn from_hex(s: &str) -> PyResult<Self> {
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        Ok(PyFr(poseidon::parse_fr(&format!("0x{}", digits))?))
+    }
+
+    #[args(byteorder = "\"big\"")]
+    fn to_bytes<'py>(&self, py: Python<'py>, byteorder: &str) -> PyResult<&'py PyBytes> {
+        let mut out = [0u8; 32];
+        poseidon::fr_to_bytes(&self.0, big_endian(byteorder)?, &mut out);
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn hex(&self) -> String {
+        self.0.into_repr().to_string()
+    }
+
+    fn inverse(&self) -> PyResult<Self> {
+        self.0.inverse()
+            .map(PyFr)
+            .ok_or_else(|| PyZeroDivisionError::new_err("inverse of zero"))
+    }
+
+    fn __int__(&self) -> BigUint {
+        poseidon::fr_to_biguint(&self.0)
+    }
+
+    fn __index__(&self) -> BigUint {
+        self.__int__()
+    }
+
+    fn __bool__(&self) -> bool {
+        !self.0.is_zero()
+    }
+
+    fn __str__(&self) -> String {
+        self.__int__().to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Fr({})", self.__int__())
+    }
+
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        // equal to hash(int(self)), as Fr(n) == n.
+        self.__int__().into_py(py).as_ref(py).hash()
+    }
+
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp, py: Python<'_>) -> PyObject {
+        let other = match other.extract::<FieldInput>() {
+            Ok(FieldInput::Str(_)) | Err(_) => return py.NotImplemented(),
+            Ok(other) => other.to_fr(),
+        };
+        match (op, other) {
+            (CompareOp::Eq, Ok(other)) => (self.0 == other).into_py(py),
+            (CompareOp::Ne, Ok(other)) => (self.0 != other).into_py(py),
+            // ints outside the field never equal an element.
+            (CompareOp::Eq, Err(_)) => false.into_py(py),
+            (CompareOp::Ne, Err(_)) => true.into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+
+    fn __neg__(&self) -> Self {
+        let mut r = self.0;
+        r.negate();
+        PyFr(r)
+    }
+
+    fn __pos__(&self) -> Self {
+        self.clone()
+    }
+
+    fn __add__(&self, other: FieldInput) -> PyResult<Self> {
+        let mut r = self.0;
+        r.add_assign(&other.to_fr()?);
+        Ok(PyFr(r))
+    }
+
+    fn __radd__(&self, other: FieldInput) -> PyResult<Self> {
+        self.__add__(other)
+    }
+
+    fn __sub__(&self, other: FieldInput) -> PyResult<Self> {
+        let mut r = self.0;
+        r.sub_assign(&other.to_fr()?);
+        Ok(PyFr(r))
+    }
+
+    fn __rsub__(&self, other: FieldInput) -> PyResult<Self> {
+        let mut r = other.to_fr()?;
+        r.sub_assign(&self.0);
+        Ok(PyFr(r))
+    }
+
+    fn __mul__(&self, other: FieldInput) -> PyResult<Self> {
+        let mut r = self.0;
+        r.mul_assign(&other.to_fr()?);
+        Ok(PyFr(r))
+    }
+
+    fn __rmul__(&self, other: FieldInput) -> PyResult<Self> {
+        self.__mul__(other)
+    }
+
+    fn __truediv__(&self, other: FieldInput) -> PyResult<Self> {
+        let inv = PyFr(other.to_fr()?).inverse()?;
+        self.__mul__(FieldInput::Fr(inv))
+    }
+
+    fn __rtruediv__(&self, other: FieldInput) -> PyResult<Self> {
+        PyFr(other.to_fr()?).__truediv__(FieldInput::Fr(self.clone()))
+    }
+
+    fn __pow__(&self, exp: BigInt, modulo: Option<&PyAny>) -> PyResult<Self> {
+        if modulo.is_some_and(|m| !m.is_none()) {
+            return Err(PyValueError::new_err("pow() with a modulus is not supported for Fr"));
+        }
+        let base = if exp.sign() == num_bigint::Sign::Minus {
+            self.inverse()?.0
+        } else {
+            self.0
+        };
+        Ok(PyFr(base.pow(exp.magnitude().to_u64_digits())))
+    }
+}
+
+/// A Poseidon parameter set that does not depend on the per-process
+/// registry. Pickles to its compact `to_bytes` encoding, so it can be sent
+/// to `multiprocessing` workers and registered or hashed with there.
+#[pyclass(name = "PoseidonParams", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PyPoseidonParams(pub Arc<Constants>);
+
+#[pymethods]
+impl PyPoseidonParams {
+    #[new]
+    #[args(byteorder = "\"big\"")]
+    fn new(
+        t: usize, n_rounds_f: usize, n_rounds_p: usize,
+        c: Vec<FieldInput>, m: Vec<Vec<FieldInput>>, byteorder: &str) -> PyResult<Self> {
+        let big_endian = big_endian(byteorder)?;
+        let params = Constants::new(
+            t, n_rounds_f, n_rounds_p,
+            to_frs(&c, big_endian)?,
+            m.iter().map(|l| to_frs(l, big_endian)).collect::<Result<_, _>>()?,
+        )?;
+        Ok(PyPoseidonParams(Arc::new(params)))
+    }
+
+    /// The parameters currently registered for width `t`.
+    #[staticmethod]
+    fn registered(t: usize) -> PyResult<Self> {
+        Ok(PyPoseidonParams(Arc::new(poseidon::registered_params(t)?)))
+    }
+
+    /// circomlib's parameters for width `t`, for 2 <= t <= 9.
+    #[staticmethod]
+    fn circomlib(t: usize) -> PyResult<Self> {
+        Ok(PyPoseidonParams(Arc::new(poseidon::circomlib_params(t)?)))
+    }
+
+    /// Registers the parameters for their width in this process, as
+    /// `poseidon_params` does.
+    fn register(&self) {
+        poseidon::register_params((*self.0).clone());
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.0.width
+    }
+
+    #[getter]
+    fn n_rounds_f(&self) -> usize {
+        self.0.n_rounds_f
+    }
+
+    #[getter]
+    fn n_rounds_p(&self) -> usize {
+        self.0.n_rounds_p
+    }
+
+    #[args(data = "None", byteorder = "\"big\"")]
+    fn hasher(&self, data: Option<Vec<FieldInput>>, byteorder: &str) -> PyResult<PyPoseidon> {
+        PyPoseidon::with_params(self.0.clone(), data, byteorder)
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.0.to_bytes())
+    }
+
+    #[classmethod]
+    fn from_bytes(_cls: &PyType, b: &[u8]) -> PyResult<Self> {
+        Ok(PyPoseidonParams(Arc::new(Constants::from_bytes(b)?)))
+    }
+
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<(PyObject, (PyObject,))> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        Ok((from_bytes.into(), (self.to_bytes(py).into(),)))
+    }
+
+    fn __richcmp__(&self, other: PyRef<'_, PyPoseidonParams>, op: CompareOp, py: Python<'_>) -> PyObject {
+        let (a, b) = (&self.0, &other.0);
+        let eq = a.n_rounds_f == b.n_rounds_f && a.n_rounds_p == b.n_rounds_p && a.c == b.c && a.m == b.m;
+        eq_only(op, eq, py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PoseidonParams(t={}, n_rounds_f={}, n_rounds_p={})",
+                self.0.width, self.0.n_rounds_f, self.0.n_rounds_p)
+    }
+}
+
+/// Width `t` of the registered parameters, or a parameter set.
+#[derive(FromPyObject)]
+enum ParamsArg {
+    Params(PyPoseidonParams),
+    Width(usize),
+}
+
+/// hashlib-style incremental Poseidon hasher over the parameters
+/// registered for width `t`, or over a `PoseidonParams`, e.g.
+/// `Poseidon(3, [a, b]).update([c]).hexdigest()`. Pickling keeps the
+/// parameters and the absorbed state, not the input.
+#[pyclass(name = "Poseidon", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PyPoseidon(pub Sponge);
+
+impl PyPoseidon {
+    fn with_params(params: Arc<Constants>, data: Option<Vec<FieldInput>>, byteorder: &str) -> PyResult<Self> {
+        let big_endian = big_endian(byteorder)?;
+        let mut sponge = Sponge::new(params);
+        if let Some(data) = data {
+            sponge.absorb(&to_frs(&data, big_endian)?);
+        }
+        Ok(PyPoseidon(sponge))
+    }
+}
+
+#[pymethods]
+impl PyPoseidon {
+    #[new]
+    #[args(data = "None", byteorder = "\"big\"")]
+    fn new(t: ParamsArg, data: Option<Vec<FieldInput>>, byteorder: &str) -> PyResult<Self> {
+        let params = match t {
+            ParamsArg::Params(p) => p.0,
+            ParamsArg::Width(t) => Arc::new(poseidon::registered_params(t)?),
+        };
+        PyPoseidon::with_params(params, data, byteorder)
+    }
+
+    #[getter]
+    fn params(&self) -> PyPoseidonParams {
+        PyPoseidonParams(self.0.params().clone())
+    }
+
+    #[getter]
+    fn name(&self) -> &'static str {
+        "poseidon"
+    }
+
+    #[getter]
+    fn digest_size(&self) -> usize {
+        32
+    }
+
+    /// Elements absorbed per permutation.
+    #[getter]
+    fn block_size(&self) -> usize {
+        self.0.rate()
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.0.params().width
+    }
+
+    #[args(byteorder = "\"big\"")]
+    fn update<'p>(
+        mut slf: PyRefMut<'p, Self>, elements: Vec<FieldInput>, byteorder: &str) -> PyResult<PyRefMut<'p, Self>> {
+        slf.0.absorb(&to_frs(&elements, big_endian(byteorder)?)?);
+        Ok(slf)
+    }
+
+    fn copy(&self) -> Self {
+        self.clone()
+    }
+
+    /// The first output element as 32 big-endian bytes.
+    fn digest<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let mut out = [0u8; 32];
+        poseidon::fr_to_bytes(&self.0.squeeze(1)?[0], true, &mut out);
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn hexdigest(&self) -> PyResult<String> {
+        let mut out = [0u8; 32];
+        poseidon::fr_to_bytes(&self.0.squeeze(1)?[0], true, &mut out);
+        Ok(out.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    fn squeeze(&self, n: usize) -> PyResult<Vec<PyFr>> {
+        Ok(self.0.squeeze(n)?.into_iter().map(PyFr).collect())
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.0.to_bytes())
+    }
+
+    #[classmethod]
+    fn from_bytes(_cls: &PyType, b: &[u8]) -> PyResult<Self> {
+        Ok(PyPoseidon(Sponge::from_bytes(b)?))
+    }
+
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<(PyObject, (PyObject,))> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        Ok((from_bytes.into(), (self.to_bytes(py).into(),)))
+    }
+}
+
+fn point_to_bytes(p: &Point, out: &mut [u8]) {
+    poseidon::fr_to_bytes(&p.x, false, &mut out[..32]);
+    poseidon::fr_to_bytes(&p.y, false, &mut out[32..64]);
+}
+
+fn point_from_bytes(b: &[u8]) -> Result<Point, Error> {
+    Point::new(
+        poseidon::fr_from_bytes(&b[..32], false)?,
+        poseidon::fr_from_bytes(&b[32..64], false)?,
+    )
+}
+
+fn expect_len(b: &[u8], n: usize) -> PyResult<()> {
+    if b.len() != n {
+        return Err(PyValueError::new_err(format!("expected {} bytes, got {}", n, b.len())));
+    }
+    Ok(())
+}
+
+fn eq_only(op: CompareOp, eq: bool, py: Python<'_>) -> PyObject {
+    match op {
+        CompareOp::Eq => eq.into_py(py),
+        CompareOp::Ne => (!eq).into_py(py),
+        _ => py.NotImplemented(),
+    }
+}
+
+/// An affine BabyJubJub point.
+#[pyclass(name = "Point", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PyPoint(pub Point);
+
+#[pymethods]
+impl PyPoint {
+    #[new]
+    #[args(byteorder = "\"big\"")]
+    fn new(x: FieldInput, y: FieldInput, byteorder: &str) -> PyResult<Self> {
+        let big_endian = big_endian(byteorder)?;
+        Ok(PyPoint(Point::new(x.to_fr_ordered(big_endian)?, y.to_fr_ordered(big_endian)?)?))
+    }
+
+    /// The neutral element `(0, 1)`.
+    #[staticmethod]
+    fn identity() -> Self {
+        PyPoint(Point::identity())
+    }
+
+    /// circomlib's `Base8`, the generator of the prime-order subgroup.
+    #[staticmethod]
+    fn generator() -> Self {
+        PyPoint(BASE8.clone())
+    }
+
+    /// Reads 64 bytes: `x` then `y`, each 32 bytes little-endian.
+    #[staticmethod]
+    fn from_bytes(b: &[u8]) -> PyResult<Self> {
+        expect_len(b, 64)?;
+        Ok(PyPoint(point_from_bytes(b)?))
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        PyBytes::new_with(py, 64, |out| {
+            point_to_bytes(&self.0, out);
+            Ok(())
+        })
+    }
+
+    /// circomlib's `packPoint`: 32 bytes, `y` little-endian with the sign
+    /// of `x` in the top bit.
+    fn pack<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.0.compress())
+    }
+
+    /// circomlib's `unpackPoint`.
+    #[staticmethod]
+    fn unpack(b: &[u8]) -> PyResult<Self> {
+        expect_len(b, 32)?;
+        let mut bb = [0u8; 32];
+        bb.copy_from_slice(b);
+        Ok(PyPoint(babyjubjub::decompress_point(bb)?))
+    }
+
+    #[getter]
+    fn x(&self) -> PyFr {
+        PyFr(self.0.x)
+    }
+
+    #[getter]
+    fn y(&self) -> PyFr {
+        PyFr(self.0.y)
+    }
+
+    fn is_identity(&self) -> bool {
+        self.0.is_identity()
+    }
+
+    /// Whether the point is in the prime-order subgroup generated by `Base8`.
+    fn in_subgroup(&self, py: Python<'_>) -> bool {
+        py.allow_threads(|| self.0.in_subgroup())
+    }
+
+    /// `8 * self`, in the subgroup.
+    fn mul_by_cofactor(&self) -> Self {
+        PyPoint(self.0.mul_by_cofactor())
+    }
+
+    fn __neg__(&self) -> Self {
+        PyPoint(-&self.0)
+    }
+
+    fn __add__(&self, other: PyRef<'_, PyPoint>) -> Self {
+        PyPoint(&self.0 + &other.0)
+    }
+
+    fn __sub__(&self, other: PyRef<'_, PyPoint>) -> Self {
+        PyPoint(&self.0 - &other.0)
+    }
+
+    fn __mul__(&self, py: Python<'_>, n: BigInt) -> PyResult<Self> {
+        if n.sign() == num_bigint::Sign::Minus {
+            return Err(PyValueError::new_err("negative scalar"));
+        }
+        Ok(PyPoint(py.allow_threads(|| self.0.mul_scalar(&n))))
+    }
+
+    fn __rmul__(&self, py: Python<'_>, n: BigInt) -> PyResult<Self> {
+        self.__mul__(py, n)
+    }
+
+    fn __richcmp__(&self, other: PyRef<'_, PyPoint>, op: CompareOp, py: Python<'_>) -> PyObject {
+        eq_only(op, self.0 == other.0, py)
+    }
+
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        let xy: PyObject = (self.x().__int__(), self.y().__int__()).into_py(py);
+        xy.as_ref(py).hash()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Point({}, {})", self.x().__int__(), self.y().__int__())
+    }
+}
+
+/// An EdDSA signature: the point `R8` and the scalar `S`.
+#[pyclass(name = "Signature", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PySignature(pub Signature);
+
+#[pymethods]
+impl PySignature {
+    #[new]
+    #[args(byteorder = "\"big\"")]
+    fn new(r8: PyPoint, s: FieldInput, byteorder: &str) -> PyResult<Self> {
+        Ok(PySignature(Signature::new(r8.0, &s.to_bigint(big_endian(byteorder)?)?)?))
+    }
+
+    /// Reads 96 bytes: `R8` as in `Point.from_bytes`, then `S` little-endian.
+    #[staticmethod]
+    fn from_bytes(b: &[u8]) -> PyResult<Self> {
+        expect_len(b, 96)?;
+        let r_b8 = point_from_bytes(&b[..64])?;
+        Ok(PySignature(Signature::new(r_b8, &BigInt::from_bytes_le(Sign::Plus, &b[64..]))?))
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        PyBytes::new_with(py, 96, |out| {
+            point_to_bytes(&self.0.r_b8, &mut out[..64]);
+            out[64..].copy_from_slice(&self.0.s.to_bytes());
+            Ok(())
+        })
+    }
+
+    /// circomlib's `packSignature`: 64 bytes, `R8` packed then `S`
+    /// little-endian.
+    fn pack<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.0.compress())
+    }
+
+    /// circomlib's `unpackSignature`.
+    #[staticmethod]
+    fn unpack(b: &[u8]) -> PyResult<Self> {
+        expect_len(b, 64)?;
+        let mut bb = [0u8; 64];
+        bb.copy_from_slice(b);
+        Ok(PySignature(babyjubjub::decompress_signature(&bb)?))
+    }
+
+    #[getter]
+    fn r8(&self) -> PyPoint {
+        PyPoint(self.0.r_b8.clone())
+    }
+
+    #[getter]
+    fn s(&self) -> BigInt {
+        self.0.s.to_bigint()
+    }
+
+    fn __richcmp__(&self, other: PyRef<'_, PySignature>, op: CompareOp, py: Python<'_>) -> PyObject {
+        let eq = self.0 == other.0;
+        eq_only(op, eq, py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Signature({}, {})", self.r8().__repr__(), self.s())
+    }
+}
+
+/// An EdDSA public key, the point `A`.
+#[pyclass(name = "PublicKey", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PyPublicKey(pub Point);
+
+#[pymethods]
+impl PyPublicKey {
+    #[new]
+    fn new(point: PyPoint) -> Self {
+        PyPublicKey(point.0)
+    }
+
+    #[staticmethod]
+    fn from_bytes(b: &[u8]) -> PyResult<Self> {
+        Ok(PyPublicKey(PyPoint::from_bytes(b)?.0))
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        PyPoint(self.0.clone()).to_bytes(py)
+    }
+
+    /// The point packed as in `Point.pack`.
+    fn pack<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyPoint(self.0.clone()).pack(py)
+    }
+
+    #[staticmethod]
+    fn unpack(b: &[u8]) -> PyResult<Self> {
+        Ok(PyPublicKey(PyPoint::unpack(b)?.0))
+    }
+
+    #[getter]
+    fn point(&self) -> PyPoint {
+        PyPoint(self.0.clone())
+    }
+
+    /// Verifies `sig` over the field element `msg` as circomlib's
+    /// `verifyPoseidon`.
+    #[args(byteorder = "\"big\"")]
+    fn verify(
+        &self, py: Python<'_>, msg: FieldInput, sig: PyRef<'_, PySignature>, byteorder: &str) -> PyResult<bool> {
+        let m = msg.to_fr_ordered(big_endian(byteorder)?)?;
+        let sig = sig.0.clone();
+        Ok(py.allow_threads(|| babyjubjub::verify(&self.0, &sig, &m)))
+    }
+
+    /// Like `verify`, but raises `SignatureRejected` with the reason
+    /// instead of returning False.
+    #[args(byteorder = "\"big\"")]
+    fn check(
+        &self, py: Python<'_>, msg: FieldInput, sig: PyRef<'_, PySignature>, byteorder: &str) -> PyResult<()> {
+        let m = msg.to_fr_ordered(big_endian(byteorder)?)?;
+        let sig = sig.0.clone();
+        Ok(py.allow_threads(|| babyjubjub::verify_poseidon(&self.0, &sig, &m))?)
+    }
+
+    fn __richcmp__(&self, other: PyRef<'_, PyPublicKey>, op: CompareOp, py: Python<'_>) -> PyObject {
+        eq_only(op, self.0 == other.0, py)
+    }
+
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        self.point().__hash__(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PublicKey({}, {})", self.point().x().__int__(), self.point().y().__int__())
+    }
+}
+
+/// An EdDSA private key, the 32-byte seed circomlib and go-iden3-crypto
+/// expand with Blake-512.
+#[pyclass(name = "PrivateKey", module = "crypto_rs")]
+#[derive(Clone)]
+pub struct PyPrivateKey(pub PrivateKey);
+
+#[pymethods]
+impl PyPrivateKey {
+    #[new]
+    fn new(key: &[u8]) -> PyResult<Self> {
+        expect_len(key, 32)?;
+        let mut k = [0u8; 32];
+        k.copy_from_slice(key);
+        Ok(PyPrivateKey(PrivateKey::import(k)))
+    }
+
+    /// A new key from `os.urandom`.
+    #[staticmethod]
+    fn generate(py: Python<'_>) -> PyResult<Self> {
+        let key: Vec<u8> = py.import("os")?.call_method1("urandom", (32,))?.extract()?;
+        PyPrivateKey::new(&key)
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.0.key())
+    }
+
+    /// The scalar of the public key, reduced mod the subgroup order.
+    #[getter]
+    fn scalar(&self) -> BigInt {
+        self.0.scalar_key().to_bigint()
+    }
+
+    fn public_key(&self, py: Python<'_>) -> PyPublicKey {
+        PyPublicKey(py.allow_threads(|| self.0.public()))
+    }
+
+    /// Signs the field element `msg` as circomlib's `signPoseidon`.
+    #[args(byteorder = "\"big\"")]
+    fn sign(&self, py: Python<'_>, msg: FieldInput, byteorder: &str) -> PyResult<PySignature> {
+        let m = msg.to_fr_ordered(big_endian(byteorder)?)?;
+        Ok(PySignature(py.allow_threads(|| self.0.sign_poseidon(&m))))
+    }
+
+    fn __richcmp__(&self, other: PyRef<'_, PyPrivateKey>, op: CompareOp, py: Python<'_>) -> PyObject {
+        eq_only(op, self.0.key() == other.0.key(), py)
+    }
+
+    fn __repr__(&self) -> String {
+        "PrivateKey(<hidden>)".to_string()
+    }
+}
+
+#[pyfunction(byteorder = "\"big\"")]
+pub fn poseidon_params(
+    t: usize, n_rounds_f: usize, n_rounds_p: usize,
+    c: Vec<FieldInput>, m: Vec<Vec<FieldInput>>, byteorder: &str) -> PyResult<usize> {
+    let big_endian = big_endian(byteorder)?;
+    let params = Constants::new(
+        t, n_rounds_f, n_rounds_p,
+        to_frs(&c, big_endian)?,
+        m.iter().map(|l| to_frs(l, big_endian)).collect::<Result<_, _>>()?,
+    )?;
+
+    let mut params_map = POSEIDON_PARAMS.write().unwrap();
+    params_map.insert(t, params);
+
+    Ok(&params_map[&t] as *const Constants as usize)
+}
+
+#[pyfunction(byteorder = "\"big\"", output = "\"legacy\"")]
+pub fn poseidon_hash(
+    py: Python<'_>, inp: Vec<FieldInput>, t: usize,
+    byteorder: &str, output: &str) -> PyResult<PyObject> {
+    let encoding = Encoding::new(output, byteorder)?;
+    let params_map = POSEIDON_PARAMS.read().unwrap();
+    let params = params_map.get(&t)
+        .ok_or(Error::ParamsNotInitialized(t))?;
+
+    let mut state = vec![Fr::zero(); params.width];
+
+    for (i, s) in inp.iter().zip(state.iter_mut()) {
+        *s = i.to_fr_ordered(encoding.big_endian)?;
+    }
+    Ok(encoding.encode(&poseidon::hash(params, &mut state)).into_py(py))
+}
+
+/// Hashes `inputs` with width `len(inputs) + 1`, see `poseidon`.
+#[pyfunction(init_state = "None", byteorder = "\"big\"", output = "\"legacy\"")]
+#[pyo3(name = "poseidon")]
+pub fn py_poseidon(
+    py: Python<'_>, inputs: Vec<FieldInput>, init_state: Option<FieldInput>,
+    byteorder: &str, output: &str) -> PyResult<PyObject> {
+    let encoding = Encoding::new(output, byteorder)?;
+    let inp = to_frs(&inputs, encoding.big_endian)?;
+    let init_state = init_state
+        .map(|i| i.to_fr_ordered(encoding.big_endian))
+        .transpose()?;
+
+    let h = py.allow_threads(|| poseidon::poseidon(&inp, init_state))?;
+    Ok(encoding.encode(&h).into_py(py))
+}
+
+/// Returns the first `n_outs` state elements, see `poseidon_ex`.
+#[pyfunction(init_state = "None", n_outs = "1", byteorder = "\"big\"", output = "\"legacy\"")]
+#[pyo3(name = "poseidon_ex")]
+pub fn py_poseidon_ex(
+    py: Python<'_>, inputs: Vec<FieldInput>, init_state: Option<FieldInput>, n_outs: usize,
+    byteorder: &str, output: &str) -> PyResult<Vec<PyObject>> {
+    let encoding = Encoding::new(output, byteorder)?;
+    let inp = to_frs(&inputs, encoding.big_endian)?;
+    let init_state = match init_state {
+        Some(i) => i.to_fr_ordered(encoding.big_endian)?,
+        None => Fr::zero(),
+    };
+
+    let outs = py.allow_threads(|| poseidon::poseidon_ex(&inp, init_state, n_outs))?;
+    Ok(outs.iter().map(|h| encoding.encode(h).into_py(py)).collect())
+}
+
+#[pyfunction(threads = "None", byteorder = "\"big\"", output = "\"legacy\"")]
+pub fn multi_poseidon_hash(
+    py: Python<'_>, inp: Vec<FieldInput>, t: usize,
+    threads: Option<usize>, byteorder: &str, output: &str) -> PyResult<Vec<PyObject>> {
+    let encoding = Encoding::new(output, byteorder)?;
+    let output = py.allow_threads(|| poseidon::install(threads, || {
+        let params_map = POSEIDON_PARAMS.read().unwrap();
+        let params = params_map.get(&t)
+            .ok_or(Error::ParamsNotInitialized(t))?;
+
+        let inp = inp
+            .par_iter()
+            .map(|i| i.to_fr_ordered(encoding.big_endian))
+            .collect::<Result<Vec<Fr>, Error>>()?;
+
+        Ok::<_, PyErr>(poseidon::hash_many(params, &inp)?
+            .par_iter()
+            .map(|h| encoding.encode(h))
+            .collect::<Vec<Encoded>>())
+    }))??;
+
+    Ok(output.into_iter().map(|h| h.into_py(py)).collect())
+}
+
+/// Hashes each list with the parameters of its length plus one; each hash
+/// equals `poseidon` of the same list.
+#[pyfunction(threads = "None", byteorder = "\"big\"", output = "\"legacy\"")]
+pub fn batch_poseidon_hash(
+    py: Python<'_>, inp: Vec<Vec<FieldInput>>,
+    threads: Option<usize>, byteorder: &str, output: &str) -> PyResult<Vec<PyObject>> {
+    let encoding = Encoding::new(output, byteorder)?;
+    let output = py.allow_threads(|| poseidon::install(threads, || {
+        let inp = inp
+            .par_iter()
+            .map(|item| to_frs(item, encoding.big_endian))
+            .collect::<Result<Vec<Vec<Fr>>, Error>>()?;
+
+        let params_map = POSEIDON_PARAMS.read().unwrap();
+        Ok::<_, PyErr>(poseidon::hash_ragged(&params_map, &inp)?
+            .par_iter()
+            .map(|h| encoding.encode(h))
+            .collect::<Vec<Encoded>>())
+    }))??;
+
+    Ok(output.into_iter().map(|h| h.into_py(py)).collect())
+}
+
+/// Batch hash over a `uint64` buffer of four limbs per element, least
+/// significant limb first, e.g. a NumPy array of shape `(n, 4)`. The
+/// buffer is copied while holding the GIL, as other threads may write to
+/// it once it is released.
+/// Returns the hashes as bytes in the same layout, ready for
+/// `numpy.frombuffer(out, dtype=numpy.uint64).reshape(-1, 4)`.
+#[pyfunction(threads = "None")]
+pub fn multi_poseidon_hash_limbs(
+    py: Python<'_>, inp: PyBuffer<u64>, t: usize, threads: Option<usize>) -> PyResult<PyObject> {
+    let limbs = inp.to_vec(py)?;
+    if !limbs.len().is_multiple_of(4) {
+        return Err(Error::InvalidParams(
+            "buffer length is not a multiple of 4 limbs".to_string()).into());
+    }
+
+    let output = py.allow_threads(|| poseidon::install(threads, || {
+        poseidon::hash_items(t, limbs.len() / 4, |i| poseidon::fr_from_limbs(&limbs[i * 4..i * 4 + 4]))
+    }))??;
+
+    let bytes = PyBytes::new_with(py, output.len() * 32, |b| {
+        b.par_chunks_mut(32)
+            .zip(output.par_iter())
+            .for_each(|(b, h)| {
+                h.into_repr().as_ref()
+                    .iter()
+                    .zip(b.chunks_mut(8))
+                    .for_each(|(l, b)| b.copy_from_slice(&l.to_ne_bytes()));
+            });
+        Ok(())
+    })?;
+    Ok(bytes.into())
+}
+
+/// Batch hash over a buffer of 32-byte rows in `byteorder`, copied as in
+/// `multi_poseidon_hash_limbs`. Returns the hashes as rows of the same
+/// encoding.
+#[pyfunction(threads = "None", byteorder = "\"big\"")]
+pub fn multi_poseidon_hash_bytes(
+    py: Python<'_>, inp: PyBuffer<u8>, t: usize,
+    threads: Option<usize>, byteorder: &str) -> PyResult<PyObject> {
+    let big_endian = big_endian(byteorder)?;
+    let rows = inp.to_vec(py)?;
+    if !rows.len().is_multiple_of(32) {
+        return Err(Error::InvalidParams(
+            "buffer length is not a multiple of 32 bytes".to_string()).into());
+    }
+
+    let output = py.allow_threads(|| poseidon::install(threads, || {
+        poseidon::hash_items(t, rows.len() / 32, |i| poseidon::fr_from_bytes(&rows[i * 32..i * 32 + 32], big_endian))
+    }))??;
+
+    let bytes = PyBytes::new_with(py, output.len() * 32, |b| {
+        b.par_chunks_mut(32)
+            .zip(output.par_iter())
+            .for_each(|(b, h)| poseidon::fr_to_bytes(h, big_endian, b));
+        Ok(())
+    })?;
+    Ok(bytes.into())
+}
+
+/// Reads `[A.x, A.y, R8.x, R8.y, S, msg]`.
+fn signed_message(inps: &[FieldInput; 6], big_endian: bool) -> Result<(Point, Signature, Fr), Error> {
+    let [x1, x2, rx, ry, ss, msg] = inps;
+    let (x1, x2) = (x1.to_fr_ordered(big_endian)?, x2.to_fr_ordered(big_endian)?);
+    let (rx, ry) = (rx.to_fr_ordered(big_endian)?, ry.to_fr_ordered(big_endian)?);
+    let s = ss.to_bigint(big_endian)?;
+    let m = msg.to_fr_ordered(big_endian)?;
+
+    let pk = Point::new(x1, x2)
+        .map_err(|_| Error::SignatureRejected(Rejection::PublicKeyNotOnCurve))?;
+    let r_b8 = Point::new(rx, ry)
+        .map_err(|_| Error::SignatureRejected(Rejection::RNotOnCurve))?;
+    Ok((pk, Signature::new(r_b8, &s)?, m))
+}
+
+/// `signed_message`, with a signature rejected while reading it as `None`.
+fn signed_message_opt(inps: &[FieldInput; 6], big_endian: bool) -> Result<Option<(Point, Signature, Fr)>, Error> {
+    match signed_message(inps, big_endian) {
+        Ok(item) => Ok(Some(item)),
+        Err(Error::SignatureRejected(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The original check of `[A.x, A.y, R.x, R.y, S, msg]`, unchanged for
+/// existing callers: `S * B8 == R + hm * A` with the width 6 parameters
+/// registered through `poseidon_params`, see `babyjubjub::verify_legacy`.
+/// It does not accept circomlib signatures; `PublicKey.verify` and
+/// `eddsa_verify_batch` check those. Deprecated: it warns with a
+/// `DeprecationWarning`, as `S + l` passes it wherever `S` does.
+#[pyfunction(byteorder = "\"big\"")]
+pub fn eddsa_verify(py: Python<'_>, inps: [FieldInput; 6], byteorder: &str) -> PyResult<bool> {
+    PyErr::warn(
+        py,
+        py.import("builtins")?.getattr("DeprecationWarning")?,
+        "eddsa_verify is deprecated, use PublicKey.verify or eddsa_verify_batch",
+        1,
+    )?;
+    let big_endian = big_endian(byteorder)?;
+    let [x1, x2, rx, ry, ss, msg] = &inps;
+    let (x1, x2) = (x1.to_fr_ordered(big_endian)?, x2.to_fr_ordered(big_endian)?);
+    let (rx, ry) = (rx.to_fr_ordered(big_endian)?, ry.to_fr_ordered(big_endian)?);
+    let s = ss.to_bigint(big_endian)?;
+    let m = msg.to_fr_ordered(big_endian)?;
+    let (pk, r) = match (Point::new(x1, x2), Point::new(rx, ry)) {
+        (Ok(pk), Ok(r)) => (pk, r),
+        _ => return Ok(false),
+    };
+
+    #[allow(deprecated)]
+    Ok(py.allow_threads(|| babyjubjub::verify_legacy(&pk, &r, &s, &m))?)
+}
+
+/// Verifies each `[A.x, A.y, R8.x, R8.y, S, msg]` item as circomlib's
+/// `verifyPoseidon`, like `PublicKey.verify` and unlike the legacy
+/// `eddsa_verify`, in parallel and with a single combined check when all
+/// are valid.
+#[pyfunction(threads = "None", byteorder = "\"big\"")]
+pub fn eddsa_verify_batch(
+    py: Python<'_>, inps: Vec<[FieldInput; 6]>,
+    threads: Option<usize>, byteorder: &str) -> PyResult<Vec<bool>> {
+    let big_endian = big_endian(byteorder)?;
+    py.allow_threads(|| poseidon::install(threads, || {
+        let parsed = inps
+            .par_iter()
+            .map(|i| signed_message_opt(i, big_endian))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let items: Vec<_> = parsed.iter().flatten().cloned().collect();
+        let mut results = babyjubjub::verify_batch(&items).into_iter();
+        Ok(parsed.iter().map(|p| p.is_some() && results.next().unwrap().is_ok()).collect())
+    }))?
+}